@@ -0,0 +1,34 @@
+//! Compares opening groups one by one with opening them in a `with_batch`.
+//!
+//! Markers go to stdout, timings to stderr: `cargo bench --bench batch > /dev/null`.
+
+use std::time::{Duration, Instant};
+
+const GROUPS: usize = 10_000;
+const ROUNDS: usize = 5;
+
+fn groups() {
+    for i in 0..GROUPS {
+        let _g = ci_group::open(format!("step {i}"));
+    }
+}
+
+fn best_of(run: impl Fn()) -> Duration {
+    (0..ROUNDS)
+        .map(|_| {
+            let start = Instant::now();
+            run();
+            start.elapsed()
+        })
+        .min()
+        .unwrap_or_default()
+}
+
+fn main() {
+    std::env::set_var("CI_GROUP_PROVIDER", "github");
+    let per_call = best_of(groups);
+    let batched = best_of(|| ci_group::with_batch(|_| groups()));
+    eprintln!("{GROUPS} groups, best of {ROUNDS}:");
+    eprintln!("  per-call locking: {per_call:?}");
+    eprintln!("  batched:          {batched:?}");
+}
@@ -0,0 +1,67 @@
+//! The `#[ci_group]` attribute, re-exported by the `ci_group` crate under its `macros` feature.
+
+use proc_macro::TokenStream;
+use proc_macro2::Span;
+use quote::quote;
+use syn::{parse_macro_input, Error, ItemFn, LitStr};
+
+/// Wraps a function body in a log group, titled with the given string or the function's name.
+///
+/// The group opens when the body starts and closes when it ends, however it ends: an early
+/// `return`, `?`, or a panic. An `async fn` opens its group when the future is first polled and
+/// closes it when the future completes or is dropped.
+///
+/// ```rust
+/// use ci_group::ci_group;
+///
+/// #[ci_group("Build")]
+/// fn build() -> std::io::Result<()> {
+///     println!("Building...");
+///     Ok(())
+/// }
+///
+/// #[ci_group]
+/// fn test() {
+///     println!("Testing..."); // in a group titled "test"
+/// }
+/// # build().unwrap();
+/// # test();
+/// ```
+///
+/// The expansion names `::ci_group`, so the crate can't be renamed in `Cargo.toml`. The title must
+/// be a string literal:
+///
+/// ```compile_fail
+/// #[ci_group::ci_group(42)]
+/// fn build() {}
+/// ```
+///
+/// A `const fn` can't open a group:
+///
+/// ```compile_fail
+/// #[ci_group::ci_group]
+/// const fn answer() -> u32 {
+///     42
+/// }
+/// ```
+#[proc_macro_attribute]
+pub fn ci_group(attr: TokenStream, item: TokenStream) -> TokenStream {
+    let mut function = parse_macro_input!(item as ItemFn);
+    let title = if attr.is_empty() {
+        LitStr::new(&function.sig.ident.to_string(), Span::call_site())
+    } else {
+        parse_macro_input!(attr as LitStr)
+    };
+    if let Some(constness) = function.sig.constness {
+        return Error::new_spanned(constness, "#[ci_group] can't be used on a `const fn`")
+            .to_compile_error()
+            .into();
+    }
+
+    let body = &function.block;
+    *function.block = syn::parse_quote!({
+        let __ci_group_guard = ::ci_group::open(#title);
+        #body
+    });
+    quote!(#function).into()
+}
@@ -0,0 +1,779 @@
+//! Workflow commands: annotations that show up in the CI UI, debug messages, secret masking, problem
+//! matchers, pausing command processing, the job summary, and step outputs and environment.
+//!
+//! GitHub Actions gets the full `::error file=...::message` form. Azure Pipelines maps errors and
+//! warnings to `##[error]`/`##[warning]` and prints notices as plain output. Everywhere else the bare
+//! message goes to stderr.
+
+use std::collections::hash_map::RandomState;
+use std::fs::OpenOptions;
+use std::hash::BuildHasher;
+use std::io::Write;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Once;
+use std::time::SystemTime;
+
+use crate::markers::{
+    azure_escape, close_marker, encode_command_data, encode_property, open_marker,
+};
+use crate::{open_github_group, Provider, Sink};
+
+/// Severity of an [`Annotation`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Level {
+    Error,
+    Warning,
+    Notice,
+}
+
+impl Level {
+    fn command(self) -> &'static str {
+        match self {
+            Level::Error => "error",
+            Level::Warning => "warning",
+            Level::Notice => "notice",
+        }
+    }
+}
+
+/// A message surfaced in the CI UI, optionally tied to a source location.
+///
+/// ```rust
+/// ci_group::Annotation::error("mismatched types")
+///     .file("src/lib.rs")
+///     .line(12)
+///     .col(5)
+///     .title("Build failed")
+///     .emit();
+/// ```
+#[derive(Debug, Clone)]
+#[must_use = "annotations are only written by .emit()"]
+pub struct Annotation {
+    level: Level,
+    message: String,
+    file: Option<String>,
+    line: Option<u32>,
+    col: Option<u32>,
+    title: Option<String>,
+    break_out: bool,
+}
+
+impl Annotation {
+    /// Creates an annotation with the given level and message.
+    pub fn new(level: Level, message: &str) -> Self {
+        Annotation {
+            level,
+            message: message.to_owned(),
+            file: None,
+            line: None,
+            col: None,
+            title: None,
+            break_out: false,
+        }
+    }
+
+    /// Creates an error annotation.
+    pub fn error(message: &str) -> Self {
+        Self::new(Level::Error, message)
+    }
+
+    /// Creates a warning annotation.
+    pub fn warning(message: &str) -> Self {
+        Self::new(Level::Warning, message)
+    }
+
+    /// Creates a notice annotation.
+    pub fn notice(message: &str) -> Self {
+        Self::new(Level::Notice, message)
+    }
+
+    /// Source file the annotation points at.
+    pub fn file(mut self, file: &str) -> Self {
+        self.file = Some(file.to_owned());
+        self
+    }
+
+    /// 1-based line number in [`file`](Self::file).
+    pub fn line(mut self, line: u32) -> Self {
+        self.line = Some(line);
+        self
+    }
+
+    /// 1-based column number in [`file`](Self::file).
+    pub fn col(mut self, col: u32) -> Self {
+        self.col = Some(col);
+        self
+    }
+
+    /// Custom title shown above the message.
+    pub fn title(mut self, title: &str) -> Self {
+        self.title = Some(title.to_owned());
+        self
+    }
+
+    /// Closes the open GitHub group around the annotation and reopens it after, so a fold can't
+    /// hide it.
+    ///
+    /// Off by default. The output that follows stays grouped under the same title. Only GitHub
+    /// Actions, whose groups don't nest, is affected; the markers go where the annotation goes.
+    pub fn break_out(mut self, break_out: bool) -> Self {
+        self.break_out = break_out;
+        self
+    }
+
+    /// Writes the annotation for the detected provider.
+    pub fn emit(&self) {
+        let provider = Provider::current();
+        let line = self.render(provider);
+        if let Some(title) = self.break_out_of(provider) {
+            Sink::selected().write_marker(format_args!(
+                "{}{line}\n{}",
+                close_marker(provider, &title),
+                open_marker(provider, &title)
+            ));
+            return;
+        }
+        match provider {
+            Provider::GitHub | Provider::Azure => {
+                Sink::selected().write_marker(format_args!("{line}\n"))
+            }
+            _ => Sink::Stderr.write_marker(format_args!("{line}\n")),
+        }
+    }
+
+    /// The group to close around the annotation, if [`break_out`](Self::break_out) applies.
+    fn break_out_of(&self, provider: Provider) -> Option<String> {
+        match provider {
+            Provider::GitHub if self.break_out => open_github_group(),
+            _ => None,
+        }
+    }
+
+    /// Formats the annotation as [`emit`](Self::emit) would for `provider`, without the trailing
+    /// newline. Pure, so the escaping can be tested on its own.
+    ///
+    /// One line, except on Azure, where each line of a multi-line message gets its own prefix.
+    ///
+    /// ```rust
+    /// use ci_group::{Annotation, Provider};
+    ///
+    /// let annotation = Annotation::error("boom").file("src/lib.rs");
+    /// assert_eq!(annotation.render(Provider::GitHub), "::error file=src/lib.rs::boom");
+    /// assert_eq!(annotation.render(Provider::Azure), "##[error]boom");
+    /// ```
+    pub fn render(&self, provider: Provider) -> String {
+        match provider {
+            Provider::GitHub => {
+                let mut props = Vec::new();
+                if let Some(file) = &self.file {
+                    props.push(format!("file={}", encode_property(file)));
+                }
+                if let Some(line) = self.line {
+                    props.push(format!("line={line}"));
+                }
+                if let Some(col) = self.col {
+                    props.push(format!("col={col}"));
+                }
+                if let Some(title) = &self.title {
+                    props.push(format!("title={}", encode_property(title)));
+                }
+
+                let command = self.level.command();
+                let message = encode_command_data(&self.message);
+                if props.is_empty() {
+                    format!("::{command}::{message}")
+                } else {
+                    format!("::{command} {}::{message}", props.join(","))
+                }
+            }
+            Provider::Azure => {
+                let prefix = match self.level {
+                    Level::Error => "##[error]",
+                    Level::Warning => "##[warning]",
+                    Level::Notice => "",
+                };
+                azure_lines(prefix, &self.message)
+            }
+            _ => self.message.clone(),
+        }
+    }
+}
+
+/// Escapes `message` for Azure and starts each of its lines with `prefix`, so none of them can
+/// carry a logging command or escape into a plain log line.
+fn azure_lines(prefix: &str, message: &str) -> String {
+    let message = azure_escape(message).replace("\r\n", "\n");
+    let lines: Vec<String> = message
+        .split(['\r', '\n'])
+        .map(|line| format!("{prefix}{line}"))
+        .collect();
+    lines.join("\n")
+}
+
+/// Emits an error annotation. See [`Annotation`] for file/line details.
+pub fn error(msg: &str) {
+    Annotation::error(msg).emit();
+}
+
+/// Emits a warning annotation. See [`Annotation`] for file/line details.
+pub fn warning(msg: &str) {
+    Annotation::warning(msg).emit();
+}
+
+/// Emits a notice annotation. See [`Annotation`] for file/line details.
+pub fn notice(msg: &str) {
+    Annotation::notice(msg).emit();
+}
+
+/// Installs a panic hook that reports panics as error annotations, then runs the previous hook.
+///
+/// The annotation carries the panic message and location, so a panic inside a collapsed group
+/// still surfaces in the CI UI. It's only written on GitHub Actions and Azure Pipelines; elsewhere
+/// the previous hook's message is all there is. Calling this more than once installs one hook.
+///
+/// ```rust
+/// ci_group::install_panic_group();
+/// ```
+pub fn install_panic_group() {
+    static INSTALLED: Once = Once::new();
+
+    INSTALLED.call_once(|| {
+        let previous = std::panic::take_hook();
+        std::panic::set_hook(Box::new(move |info| {
+            if matches!(Provider::current(), Provider::GitHub | Provider::Azure) {
+                let payload = info.payload();
+                let message = payload
+                    .downcast_ref::<&str>()
+                    .copied()
+                    .or_else(|| payload.downcast_ref::<String>().map(String::as_str))
+                    .unwrap_or("Box<dyn Any>");
+                let mut annotation = Annotation::error(message).title("panic");
+                if let Some(location) = info.location() {
+                    annotation = annotation
+                        .file(location.file())
+                        .line(location.line())
+                        .col(location.column());
+                }
+                annotation.emit();
+            }
+            previous(info);
+        }));
+    });
+}
+
+/// Writes a debug message that the CI only shows when debug logging is enabled.
+///
+/// GitHub Actions gets `::debug::` and Azure Pipelines `##[debug]`; both runners hide these lines
+/// unless step debug logging (`ACTIONS_STEP_DEBUG` / `system.debug`) is on, so this doesn't check
+/// `RUNNER_DEBUG` itself. On Azure, each line of the message gets its own `##[debug]`. Other
+/// providers and local runs print nothing.
+pub fn debug(msg: &str) {
+    if let Some(command) = debug_command(Provider::current(), msg) {
+        Sink::selected().write_marker(format_args!("{command}\n"));
+    }
+}
+
+fn debug_command(provider: Provider, msg: &str) -> Option<String> {
+    match provider {
+        Provider::GitHub => Some(format!("::debug::{}", encode_command_data(msg))),
+        Provider::Azure => Some(azure_lines("##[debug]", msg)),
+        _ => None,
+    }
+}
+
+/// Registers a secret so GitHub Actions redacts it from all later log output.
+///
+/// Only GitHub supports masking an arbitrary value at runtime; on every other provider this is a
+/// no-op. On Azure, mark pipeline variables as secret instead.
+pub fn mask(value: &str) {
+    if let Some(command) = mask_command(Provider::current(), value) {
+        Sink::selected().write_marker(format_args!("{command}\n"));
+    }
+}
+
+fn mask_command(provider: Provider, value: &str) -> Option<String> {
+    match provider {
+        Provider::GitHub => Some(format!("::add-mask::{}", encode_command_data(value))),
+        _ => None,
+    }
+}
+
+/// Registers a GitHub Actions problem matcher, the JSON file at `path`, so later tool output that
+/// matches it becomes annotations.
+///
+/// A no-op on other providers. Undo it with [`remove_matcher`].
+///
+/// ```rust
+/// ci_group::add_matcher(".github/rustc-matcher.json");
+/// ci_group::group!("Build", {
+///     // compiler errors show up as annotations
+/// });
+/// ci_group::remove_matcher("rustc");
+/// ```
+pub fn add_matcher(path: &str) {
+    write_github_command(add_matcher_command(Provider::current(), path));
+}
+
+/// Removes the problem matcher registered under `owner`, the name in its JSON file.
+///
+/// A no-op on other providers.
+pub fn remove_matcher(owner: &str) {
+    write_github_command(remove_matcher_command(Provider::current(), owner));
+}
+
+fn write_github_command(command: Option<String>) {
+    if let Some(command) = command {
+        Sink::selected().write_marker(format_args!("{command}\n"));
+    }
+}
+
+fn add_matcher_command(provider: Provider, path: &str) -> Option<String> {
+    match provider {
+        Provider::GitHub => Some(format!("::add-matcher::{}", encode_command_data(path))),
+        _ => None,
+    }
+}
+
+fn remove_matcher_command(provider: Provider, owner: &str) -> Option<String> {
+    match provider {
+        Provider::GitHub => Some(format!(
+            "::remove-matcher owner={}::",
+            encode_property(owner)
+        )),
+        _ => None,
+    }
+}
+
+/// Makes GitHub Actions treat output as plain text until the returned guard drops.
+///
+/// Use it around untrusted text, such as a tool's output, that may contain `::` lines GitHub would
+/// otherwise run as workflow commands. Writes `::stop-commands::<token>` with a fresh random token
+/// now and `::<token>::` on drop. A no-op on other providers.
+///
+/// Annotations and groups opened inside the guard are printed literally too.
+///
+/// ```rust
+/// let untrusted = "::warning::not really";
+/// let _literal = ci_group::stop_commands();
+/// println!("{untrusted}");
+/// ```
+pub fn stop_commands() -> CommandsGuard {
+    let mut sink = Sink::selected();
+    let token = match Provider::current() {
+        Provider::GitHub => Some(stop_token()),
+        _ => None,
+    };
+    if let Some(token) = &token {
+        sink.write_marker(format_args!("::stop-commands::{token}\n"));
+    }
+    CommandsGuard { token, sink }
+}
+
+/// Resumes GitHub Actions command processing when dropped. Returned by [`stop_commands`].
+#[must_use = "commands resume immediately when dropped. Bind it: let _g = stop_commands()"]
+pub struct CommandsGuard {
+    token: Option<String>,
+    sink: Sink,
+}
+
+impl Drop for CommandsGuard {
+    fn drop(&mut self) {
+        if let Some(token) = &self.token {
+            self.sink.write_marker(format_args!("::{token}::\n"));
+        }
+    }
+}
+
+/// A token no output ends with by chance, so only the guard can resume commands.
+fn stop_token() -> String {
+    static ISSUED: AtomicUsize = AtomicUsize::new(0);
+    let seed = (SystemTime::now(), ISSUED.fetch_add(1, Ordering::Relaxed));
+    format!("{:016x}", RandomState::new().hash_one(seed))
+}
+
+/// Appends Markdown to the GitHub Actions job summary.
+///
+/// Writes to the file named by `$GITHUB_STEP_SUMMARY`, followed by a newline. A no-op on other
+/// providers, when the variable is missing, or when the write fails.
+///
+/// ```rust,no_run
+/// ci_group::summary("## Results\n\n- 42 tests passed");
+/// ```
+pub fn summary(markdown: &str) {
+    if Provider::current() == Provider::GitHub {
+        append_to_env_file("GITHUB_STEP_SUMMARY", &format!("{markdown}\n"));
+    }
+}
+
+/// Sets a step output that later steps read as `steps.<id>.outputs.<name>`.
+///
+/// Appends to the file named by `$GITHUB_OUTPUT`, using the `name<<EOF` form for values that span
+/// lines. A no-op on other providers, when the variable is missing, or when the write fails.
+///
+/// A name that is empty or contains a line break, `=` or `<<` could add entries of its own, so it
+/// is rejected with a [`warning`] instead.
+///
+/// ```rust,no_run
+/// ci_group::set_output("version", "1.2.3");
+/// ```
+pub fn set_output(name: &str, value: &str) {
+    if Provider::current() == Provider::GitHub {
+        append_entry("GITHUB_OUTPUT", name, value);
+    }
+}
+
+/// Sets an environment variable for the later steps of the job, through `$GITHUB_ENV`.
+///
+/// Doesn't change this process's environment. Otherwise behaves like [`set_output`].
+pub fn set_env(name: &str, value: &str) {
+    if Provider::current() == Provider::GitHub {
+        append_entry("GITHUB_ENV", name, value);
+    }
+}
+
+/// Appends `name`'s entry to the file named by `var`, unless the name would corrupt the file.
+fn append_entry(var: &str, name: &str, value: &str) {
+    if name.is_empty() || name.contains(['\n', '\r', '=']) || name.contains("<<") {
+        warning(&format!(
+            "ci_group: ignored {var} entry with invalid name {name:?}"
+        ));
+        return;
+    }
+    append_to_env_file(var, &env_file_entry(name, value));
+}
+
+/// Formats `name=value`, or a heredoc with a delimiter that doesn't occur as a line of `value`.
+fn env_file_entry(name: &str, value: &str) -> String {
+    if !value.contains(['\n', '\r']) {
+        return format!("{name}={value}\n");
+    }
+    let mut delimiter = String::from("EOF");
+    let mut suffix = 0;
+    while value.lines().any(|line| line == delimiter) {
+        suffix += 1;
+        delimiter = format!("EOF_{suffix}");
+    }
+    format!("{name}<<{delimiter}\n{value}\n{delimiter}\n")
+}
+
+/// Appends to the file named by environment variable `var`, ignoring every failure.
+pub(crate) fn append_to_env_file(var: &str, contents: &str) {
+    let Some(path) = std::env::var_os(var) else {
+        return;
+    };
+    let _ = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .and_then(|mut file| file.write_all(contents.as_bytes()));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{temp_path, with_ci_env, Capture};
+
+    #[test]
+    fn renders_bare_github_commands() {
+        assert_eq!(
+            Annotation::error("boom").render(Provider::GitHub),
+            "::error::boom"
+        );
+        assert_eq!(
+            Annotation::warning("careful").render(Provider::GitHub),
+            "::warning::careful"
+        );
+        assert_eq!(
+            Annotation::notice("fyi").render(Provider::GitHub),
+            "::notice::fyi"
+        );
+    }
+
+    #[test]
+    fn renders_github_properties() {
+        let annotation = Annotation::error("mismatched types")
+            .file("src/lib.rs")
+            .line(12)
+            .col(5)
+            .title("Build failed");
+        assert_eq!(
+            annotation.render(Provider::GitHub),
+            "::error file=src/lib.rs,line=12,col=5,title=Build failed::mismatched types"
+        );
+    }
+
+    #[test]
+    fn escapes_message_and_properties() {
+        let annotation = Annotation::warning("100%\r\ndone: yes, really")
+            .file("C:\\a,b")
+            .title("50%: a\nb");
+        assert_eq!(
+            annotation.render(Provider::GitHub),
+            "::warning file=C%3A\\a%2Cb,title=50%25%3A a%0Ab::100%25%0D%0Adone: yes, really"
+        );
+    }
+
+    #[test]
+    fn maps_levels_on_azure() {
+        assert_eq!(
+            Annotation::error("boom").render(Provider::Azure),
+            "##[error]boom"
+        );
+        assert_eq!(
+            Annotation::warning("careful")
+                .file("a.rs")
+                .render(Provider::Azure),
+            "##[warning]careful"
+        );
+        assert_eq!(Annotation::notice("fyi").render(Provider::Azure), "fyi");
+    }
+
+    #[test]
+    fn escapes_multiline_messages_on_azure() {
+        assert_eq!(
+            Annotation::error("failed:\n##[endgroup]\r\ndone").render(Provider::Azure),
+            "##[error]failed:\n##[error]#[endgroup]\n##[error]done"
+        );
+        assert_eq!(
+            Annotation::notice("a\n##vso[task.complete]").render(Provider::Azure),
+            "a\n#vso[task.complete]"
+        );
+        assert_eq!(Annotation::error("").render(Provider::Azure), "##[error]");
+    }
+
+    #[test]
+    fn bare_message_without_annotation_support() {
+        let annotation = Annotation::error("boom").file("a.rs").line(1);
+        assert_eq!(annotation.render(Provider::None), "boom");
+        assert_eq!(annotation.render(Provider::GitLab), "boom");
+    }
+
+    #[test]
+    fn break_out_closes_and_reopens_the_group() {
+        let buf = Capture::default();
+        with_ci_env(&[("GITHUB_ACTIONS", "true")], || {
+            crate::set_writer(buf.clone());
+            let _g = crate::open("Build");
+            Annotation::error("boom").break_out(true).emit();
+            Annotation::warning("quiet").emit();
+            crate::take_writer();
+        });
+        assert_eq!(
+            buf.contents(),
+            "\n::group::Build\n\n::endgroup::\n::error::boom\n\n::group::Build\n::warning::quiet\n"
+        );
+    }
+
+    #[test]
+    fn break_out_without_a_group_is_a_plain_annotation() {
+        let buf = Capture::default();
+        with_ci_env(&[("GITHUB_ACTIONS", "true")], || {
+            crate::set_writer(buf.clone());
+            Annotation::error("boom").break_out(true).emit();
+            crate::take_writer();
+        });
+        assert_eq!(buf.contents(), "::error::boom\n");
+    }
+
+    #[test]
+    fn stop_commands_resumes_with_the_same_token() {
+        let buf = Capture::default();
+        with_ci_env(&[("GITHUB_ACTIONS", "true")], || {
+            crate::set_writer(buf.clone());
+            {
+                let _literal = stop_commands();
+                writeln!(crate::output(), "::warning::not really").unwrap();
+            }
+            crate::take_writer();
+        });
+        let out = buf.contents();
+        let lines: Vec<&str> = out.lines().collect();
+        let [stop, literal, resume] = lines[..] else {
+            panic!("{out}");
+        };
+        let token = stop.strip_prefix("::stop-commands::").unwrap();
+        assert_eq!(token.len(), 16);
+        assert_eq!(literal, "::warning::not really");
+        assert_eq!(resume, format!("::{token}::"));
+    }
+
+    #[test]
+    fn stop_commands_uses_a_fresh_token_each_time() {
+        with_ci_env(&[], || {
+            assert!(stop_commands().token.is_none());
+        });
+        assert_ne!(stop_token(), stop_token());
+    }
+
+    #[test]
+    fn masks_only_on_github() {
+        assert_eq!(
+            mask_command(Provider::GitHub, "hunter2").as_deref(),
+            Some("::add-mask::hunter2")
+        );
+        assert_eq!(
+            mask_command(Provider::GitHub, "50%\nsecret").as_deref(),
+            Some("::add-mask::50%25%0Asecret")
+        );
+        assert_eq!(mask_command(Provider::Azure, "hunter2"), None);
+        assert_eq!(mask_command(Provider::None, "hunter2"), None);
+    }
+
+    #[test]
+    fn matcher_commands_only_on_github() {
+        assert_eq!(
+            add_matcher_command(Provider::GitHub, "ci/100%.json").as_deref(),
+            Some("::add-matcher::ci/100%25.json")
+        );
+        assert_eq!(
+            remove_matcher_command(Provider::GitHub, "eslint:compact,v2").as_deref(),
+            Some("::remove-matcher owner=eslint%3Acompact%2Cv2::")
+        );
+        assert_eq!(add_matcher_command(Provider::Azure, "m.json"), None);
+        assert_eq!(remove_matcher_command(Provider::None, "eslint"), None);
+    }
+
+    #[test]
+    fn debug_messages_per_provider() {
+        assert_eq!(
+            debug_command(Provider::GitHub, "50%\ndone").as_deref(),
+            Some("::debug::50%25%0Adone")
+        );
+        assert_eq!(
+            debug_command(Provider::Azure, "cache hit").as_deref(),
+            Some("##[debug]cache hit")
+        );
+        assert_eq!(debug_command(Provider::None, "cache hit"), None);
+        assert_eq!(debug_command(Provider::Jenkins, "cache hit"), None);
+    }
+
+    #[test]
+    fn azure_debug_messages_cannot_inject_commands() {
+        assert_eq!(
+            debug_command(
+                Provider::Azure,
+                "a\n##[endgroup]\r\n##vso[task.setvariable variable=x]1"
+            )
+            .as_deref(),
+            Some("##[debug]a\n##[debug]#[endgroup]\n##[debug]#vso[task.setvariable variable=x]1")
+        );
+    }
+
+    #[test]
+    fn summary_appends_to_step_summary_file() {
+        let path = temp_path("summary");
+        std::fs::write(&path, "existing\n").unwrap();
+        let file = path.to_str().unwrap();
+        with_ci_env(
+            &[("GITHUB_ACTIONS", "true"), ("GITHUB_STEP_SUMMARY", file)],
+            || {
+                summary("## Results");
+                summary("- ok");
+            },
+        );
+        assert_eq!(
+            std::fs::read_to_string(&path).unwrap(),
+            "existing\n## Results\n- ok\n"
+        );
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn set_output_and_env_append_entries() {
+        let output = temp_path("github-output");
+        let env = temp_path("github-env");
+        with_ci_env(
+            &[
+                ("GITHUB_ACTIONS", "true"),
+                ("GITHUB_OUTPUT", output.to_str().unwrap()),
+                ("GITHUB_ENV", env.to_str().unwrap()),
+            ],
+            || {
+                set_output("version", "1.2.3");
+                set_output("notes", "line 1\nEOF\nline 3");
+                set_env("RUST_LOG", "debug");
+            },
+        );
+        assert_eq!(
+            std::fs::read_to_string(&output).unwrap(),
+            "version=1.2.3\nnotes<<EOF_1\nline 1\nEOF\nline 3\nEOF_1\n"
+        );
+        assert_eq!(std::fs::read_to_string(&env).unwrap(), "RUST_LOG=debug\n");
+        std::fs::remove_file(&output).unwrap();
+        std::fs::remove_file(&env).unwrap();
+    }
+
+    #[test]
+    fn multiline_values_use_a_heredoc() {
+        assert_eq!(env_file_entry("a", "x\ny"), "a<<EOF\nx\ny\nEOF\n");
+        assert_eq!(env_file_entry("a", "x=y"), "a=x=y\n");
+    }
+
+    #[test]
+    fn set_output_rejects_names_that_add_entries() {
+        let output = temp_path("github-output-names");
+        let env = temp_path("github-env-names");
+        let buf = Capture::default();
+        with_ci_env(
+            &[
+                ("GITHUB_ACTIONS", "true"),
+                ("GITHUB_OUTPUT", output.to_str().unwrap()),
+                ("GITHUB_ENV", env.to_str().unwrap()),
+            ],
+            || {
+                crate::set_writer(buf.clone());
+                set_output("a\nb", "1");
+                set_output("a=b", "1");
+                set_output("a<<EOF", "1");
+                set_output("", "1");
+                set_env("PATH\r\nX", "1");
+                crate::take_writer();
+            },
+        );
+        assert!(!output.exists());
+        assert!(!env.exists());
+        let out = buf.contents();
+        assert_eq!(out.matches("::warning::").count(), 5, "{out}");
+        assert!(out.contains("invalid name \"a=b\""), "{out}");
+    }
+
+    #[test]
+    fn set_output_is_a_noop_without_the_file() {
+        let output = temp_path("github-output-noop");
+        let buf = Capture::default();
+        with_ci_env(&[("GITHUB_ACTIONS", "true")], || {
+            crate::set_writer(buf.clone());
+            temp_env::with_vars_unset(["GITHUB_OUTPUT", "GITHUB_ENV"], || {
+                set_output("version", "1.2.3");
+                set_env("RUST_LOG", "debug");
+            });
+            crate::take_writer();
+        });
+        with_ci_env(
+            &[
+                ("TF_BUILD", "true"),
+                ("GITHUB_OUTPUT", output.to_str().unwrap()),
+            ],
+            || set_output("version", "1.2.3"),
+        );
+        assert!(!output.exists());
+        assert_eq!(buf.contents(), "");
+    }
+
+    #[test]
+    fn summary_is_a_noop_elsewhere() {
+        let path = temp_path("summary-noop");
+        let file = path.to_str().unwrap();
+        with_ci_env(
+            &[("TF_BUILD", "true"), ("GITHUB_STEP_SUMMARY", file)],
+            || {
+                summary("## Results");
+            },
+        );
+        assert!(!path.exists());
+
+        with_ci_env(&[("GITHUB_ACTIONS", "true")], || {
+            temp_env::with_var_unset("GITHUB_STEP_SUMMARY", || summary("## Results"));
+        });
+    }
+}
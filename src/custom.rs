@@ -0,0 +1,123 @@
+//! Providers registered at runtime, for in-house or niche CI systems the crate doesn't know.
+//!
+//! Implement [`CiProvider`] and pass it to [`register_provider`]. Detection then consults
+//! registered providers, in registration order, before the built-in ones; only
+//! `CI_GROUP_PROVIDER` takes precedence. Groups use the provider's markers like any other's, and
+//! it shows up as [`Provider::Custom`] in [`detected_provider`](crate::detected_provider).
+
+use alloc::string::String;
+
+#[cfg(feature = "std")]
+use alloc::boxed::Box;
+#[cfg(feature = "std")]
+use alloc::vec::Vec;
+#[cfg(feature = "std")]
+use std::sync::{RwLock, RwLockReadGuard};
+
+#[cfg(feature = "std")]
+use crate::Provider;
+
+/// A CI system's markers and how to recognize it. See [`register_provider`].
+///
+/// Titles arrive on one line, as [`Title`](crate::Title) normalizes them, but are otherwise not
+/// escaped: escape whatever the CI's command syntax needs.
+pub trait CiProvider: Send + Sync {
+    /// The name `CI_GROUP_PROVIDER` selects it by, and what it serializes as. Built-in names win.
+    fn name(&self) -> &'static str;
+
+    /// Returns true if the environment shows the process runs under this CI.
+    fn is_active_env(&self) -> bool;
+
+    /// The marker that opens a group titled `title`, including its line breaks.
+    fn open_marker(&self, title: &str) -> String;
+
+    /// The marker that closes a group titled `title`, or an empty string for none.
+    fn close_marker(&self, title: &str) -> String;
+}
+
+/// Handle to a provider added with [`register_provider`], carried by [`Provider::Custom`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CustomProvider(usize);
+
+#[cfg(feature = "std")]
+static REGISTRY: RwLock<Vec<&'static dyn CiProvider>> = RwLock::new(Vec::new());
+
+#[cfg(feature = "std")]
+fn registry() -> RwLockReadGuard<'static, Vec<&'static dyn CiProvider>> {
+    REGISTRY.read().unwrap_or_else(|e| e.into_inner())
+}
+
+/// Adds a provider that detection checks before the built-in ones, and returns how it appears.
+///
+/// Registered providers live until the process exits. Register them early in `main`, before the
+/// first group: the cached detection is reset here, but groups already open keep their markers.
+///
+/// ```rust
+/// use ci_group::{register_provider, CiProvider};
+///
+/// struct Acme;
+///
+/// impl CiProvider for Acme {
+///     fn name(&self) -> &'static str {
+///         "acme"
+///     }
+///     fn is_active_env(&self) -> bool {
+///         std::env::var_os("ACME_CI").is_some()
+///     }
+///     fn open_marker(&self, title: &str) -> String {
+///         format!("\n@@acme-begin {title}\n")
+///     }
+///     fn close_marker(&self, _title: &str) -> String {
+///         String::from("\n@@acme-end\n")
+///     }
+/// }
+///
+/// // Also selected by `CI_GROUP_PROVIDER=acme`.
+/// let acme = register_provider(Box::new(Acme));
+/// assert!(acme.is_active());
+/// ```
+#[cfg(feature = "std")]
+pub fn register_provider(provider: Box<dyn CiProvider>) -> Provider {
+    let mut registry = REGISTRY.write().unwrap_or_else(|e| e.into_inner());
+    registry.push(Box::leak(provider));
+    let custom = CustomProvider(registry.len() - 1);
+    drop(registry);
+    crate::forget_detected_provider();
+    Provider::Custom(custom)
+}
+
+#[cfg(feature = "std")]
+impl CustomProvider {
+    fn get(self) -> &'static dyn CiProvider {
+        registry()[self.0]
+    }
+
+    pub(crate) fn name(self) -> &'static str {
+        self.get().name()
+    }
+
+    pub(crate) fn open_marker(self, title: &str) -> String {
+        self.get().open_marker(title)
+    }
+
+    pub(crate) fn close_marker(self, title: &str) -> String {
+        self.get().close_marker(title)
+    }
+
+    /// The first registered provider whose environment is active.
+    pub(crate) fn detect() -> Option<Self> {
+        let providers: Vec<&'static dyn CiProvider> = registry().clone();
+        providers
+            .iter()
+            .position(|provider| provider.is_active_env())
+            .map(CustomProvider)
+    }
+
+    /// The first registered provider called `name`, case-insensitively.
+    pub(crate) fn find(name: &str) -> Option<Self> {
+        registry()
+            .iter()
+            .position(|provider| provider.name().eq_ignore_ascii_case(name))
+            .map(CustomProvider)
+    }
+}
@@ -0,0 +1,166 @@
+//! [`tracing`] integration: one log group per span.
+
+use std::fmt;
+use std::sync::Mutex;
+
+use tracing::field::{Field, Visit};
+use tracing::span::{Attributes, Id, Record};
+use tracing::{Metadata, Subscriber};
+use tracing_subscriber::layer::{Context, Layer};
+use tracing_subscriber::registry::LookupSpan;
+
+use crate::Group;
+
+/// A [`Layer`] that opens a [`Group`] while a span is entered.
+///
+/// The group opens when the span is entered and closes when it exits. Re-entering a span that is
+/// already entered doesn't open a second group, and a span dropped while still entered closes its
+/// group on close. Nested spans follow the crate's nesting rules (flattened on GitHub).
+///
+/// Each enter/exit cycle is its own group, so an instrumented future produces one group per poll.
+///
+/// ```rust,no_run
+/// use tracing_subscriber::layer::SubscriberExt;
+///
+/// let subscriber = tracing_subscriber::registry()
+///     .with(ci_group::CiGroupLayer::new().with_target("ci").with_field("title"));
+/// tracing::subscriber::set_global_default(subscriber).unwrap();
+///
+/// let span = tracing::info_span!(target: "ci", "build", title = "Build all");
+/// let _e = span.enter(); // ::group::Build all
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct CiGroupLayer {
+    target: Option<String>,
+    field: Option<String>,
+}
+
+impl CiGroupLayer {
+    /// Groups every span, titled by the span name.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Only groups spans whose target starts with `prefix`.
+    pub fn with_target(mut self, prefix: &str) -> Self {
+        self.target = Some(prefix.to_owned());
+        self
+    }
+
+    /// Titles groups by this span field instead of the span name, when the field is present.
+    pub fn with_field(mut self, name: &str) -> Self {
+        self.field = Some(name.to_owned());
+        self
+    }
+
+    fn wants(&self, metadata: &Metadata<'_>) -> bool {
+        self.target
+            .as_deref()
+            .is_none_or(|prefix| metadata.target().starts_with(prefix))
+    }
+}
+
+/// Per-span state stored in the span's extensions.
+struct SpanGroup {
+    title: String,
+    entered: usize,
+    group: Mutex<Option<Group>>,
+}
+
+/// Extracts the configured title field from span attributes or records.
+struct TitleVisitor<'a> {
+    field: &'a str,
+    title: Option<String>,
+}
+
+impl Visit for TitleVisitor<'_> {
+    fn record_str(&mut self, field: &Field, value: &str) {
+        if field.name() == self.field {
+            self.title = Some(value.to_owned());
+        }
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+        if field.name() == self.field {
+            self.title = Some(format!("{value:?}"));
+        }
+    }
+}
+
+impl<S> Layer<S> for CiGroupLayer
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+{
+    fn on_new_span(&self, attrs: &Attributes<'_>, id: &Id, ctx: Context<'_, S>) {
+        if !self.wants(attrs.metadata()) {
+            return;
+        }
+        let Some(span) = ctx.span(id) else { return };
+
+        let mut title = None;
+        if let Some(field) = &self.field {
+            let mut visitor = TitleVisitor { field, title: None };
+            attrs.record(&mut visitor);
+            title = visitor.title;
+        }
+
+        span.extensions_mut().insert(SpanGroup {
+            title: title.unwrap_or_else(|| attrs.metadata().name().to_owned()),
+            entered: 0,
+            group: Mutex::new(None),
+        });
+    }
+
+    fn on_record(&self, id: &Id, values: &Record<'_>, ctx: Context<'_, S>) {
+        let (Some(field), Some(span)) = (&self.field, ctx.span(id)) else {
+            return;
+        };
+        let mut extensions = span.extensions_mut();
+        let Some(state) = extensions.get_mut::<SpanGroup>() else {
+            return;
+        };
+
+        let mut visitor = TitleVisitor { field, title: None };
+        values.record(&mut visitor);
+        if let Some(title) = visitor.title {
+            state.title = title;
+        }
+    }
+
+    fn on_enter(&self, id: &Id, ctx: Context<'_, S>) {
+        let Some(span) = ctx.span(id) else { return };
+        let mut extensions = span.extensions_mut();
+        let Some(state) = extensions.get_mut::<SpanGroup>() else {
+            return;
+        };
+
+        state.entered += 1;
+        if state.entered == 1 {
+            let group = state.group.get_mut().unwrap_or_else(|e| e.into_inner());
+            *group = Some(Group::new(&state.title));
+        }
+    }
+
+    fn on_exit(&self, id: &Id, ctx: Context<'_, S>) {
+        let Some(span) = ctx.span(id) else { return };
+        let mut extensions = span.extensions_mut();
+        let Some(state) = extensions.get_mut::<SpanGroup>() else {
+            return;
+        };
+
+        state.entered = state.entered.saturating_sub(1);
+        if state.entered == 0 {
+            let group = state.group.get_mut().unwrap_or_else(|e| e.into_inner());
+            drop(group.take());
+        }
+    }
+
+    fn on_close(&self, id: Id, ctx: Context<'_, S>) {
+        let Some(span) = ctx.span(&id) else { return };
+        let mut extensions = span.extensions_mut();
+        if let Some(state) = extensions.get_mut::<SpanGroup>() {
+            let group = state.group.get_mut().unwrap_or_else(|e| e.into_inner());
+            drop(group.take());
+        }
+    }
+}
@@ -1,4 +1,4 @@
-//! A lightweight RAII library for log groups in GitHub Actions and Azure Pipelines.
+//! A lightweight RAII library for log groups in GitHub Actions, Azure Pipelines and GitLab CI.
 //!
 //! Fixes "swallowed logs" by closing groups automatically when dropped, preserving output even on panic.
 //!
@@ -20,160 +20,254 @@
 //! });
 //! ```
 //!
+//! Annotations surface errors, warnings and notices in the CI UI:
+//!
+//! ```rust
+//! ci_group::warning("deprecated config key");
+//! ci_group::Annotation::error("mismatched types")
+//!     .file("src/main.rs")
+//!     .line(12)
+//!     .emit();
+//! ```
+//!
+//! # Features
+//!
+//! - `std` (default): everything that detects the provider or writes output. Without it only
+//!   [`Provider`] and the pure formatters in [`markers`] are available, on top of `alloc`.
+//! - `async`: [`grouped`], which wraps a future in a group.
+//! - `tracing`: [`CiGroupLayer`], a `tracing-subscriber` layer that opens a group per span.
+//! - `serde`: `Serialize`/`Deserialize` for [`Provider`], as its lowercase name (`"github"`).
+//! - `macros`: the `#[ci_group]` attribute, which wraps a function body in a group.
+//! - `test-util`: `reset_provider_cache`, for tests that switch CI variables between cases,
+//!   `last_marker`, for checking what was written without capturing output, and `capture`, for
+//!   snapshot tests of everything written.
+//!
+//! # Nesting
+//!
+//! GitHub Actions can't nest groups: a second `::group::` inside an open one breaks the fold.
+//! The crate flattens instead. While a group is open on a thread, further groups opened on that
+//! thread emit nothing on open or drop, so their output simply stays inside the outer group.
+//! Azure Pipelines and the other providers nest as usual, so the same code writes real nested
+//! groups there.
+//!
+//! [`set_max_depth`] applies the same flattening at any level and on any provider, such as
+//! `Some(2)` to keep deeply nested Azure logs readable.
+//!
+//! [`current_depth`] and [`active_titles`] report the groups open on the calling thread, flattened
+//! ones included. A group moved to another thread still counts towards the thread that opened it.
+//!
+//! # Forcing a provider
+//!
+//! Set `CI_GROUP_PROVIDER` to `github`, `azure`, `gitlab`, `circleci`, `buildkite`, `teamcity`,
+//! `jenkins`, `bitbucket`, `drone`, `harness`, `appveyor`, `semaphore`, `generic` or `none` to
+//! skip auto-detection.
+//! The name of a provider added with [`register_provider`] works too. Unrecognized values are
+//! ignored.
+//!
+//! Otherwise the first match in this order wins, which matters when a job sees more than one CI's
+//! variables: GitHub Actions, Azure Pipelines, GitLab CI, CircleCI, Buildkite, Drone, AppVeyor,
+//! Semaphore (each when its flag is `true`), then TeamCity (when `TEAMCITY_VERSION` is set at
+//! all, even empty), Jenkins, Bitbucket Pipelines and Harness (when their variable isn't empty).
+//! As a last resort, any other CI that sets `CI` (to
+//! anything but `false` or `0`, as Woodpecker's `CI=woodpecker` does) gets plain banners.
+//!
+//! GitHub Enterprise Server counts as GitHub Actions: `GITHUB_SERVER_URL` is never consulted.
+//!
+//! # Turning it off
+//!
+//! Set `CI_GROUP_DISABLE` to anything but an empty string, `false` or `0` and every group writes
+//! nothing, whatever the CI, [`Mode`] or `CI_GROUP_PROVIDER`; annotations are printed as plain
+//! text. For debugging raw output, or a runner whose folding is broken, without a rebuild.
+//!
+//! Detection runs once per process, on the first group or annotation. Changing these variables
+//! afterwards has no effect, unless tests call `reset_provider_cache` (with the `test-util`
+//! feature).
+//!
 //! # Caveats
 //!
+//! - Outside CI nothing is printed. Use [`Mode::LocalBanner`] to see group boundaries locally.
+//! - Markers are written atomically, but groups open at the same time on different threads still
+//!   overlap in the log, and folds can't represent that. Open groups from one orchestrating thread.
+//! - Markers start with a line break in case earlier output didn't end its line. If all output goes
+//!   through [`output`], [`set_newline_guard`] can drop the resulting blank lines.
 //! - `std::process::exit()` skips destructors. Groups won't close. Use normal returns instead.
 //! - Don't hold `StdoutLock` across a scope where a `Group` drops (potential deadlock).
 
-use std::io::Write;
+#![cfg_attr(not(feature = "std"), no_std)]
+
+extern crate alloc;
+
+#[cfg(feature = "std")]
+mod commands;
+mod custom;
+#[cfg(feature = "tracing")]
+mod layer;
+pub mod markers;
+pub mod prelude;
+#[cfg(feature = "std")]
+mod std_impl;
+#[cfg(all(test, feature = "std"))]
+mod test_support;
+
+/// Checks the README's examples; fragments that call your own functions are marked `ignore`.
+#[cfg(all(doctest, feature = "std"))]
+#[doc = include_str!("../README.md")]
+struct ReadmeDoctests;
+
+#[cfg(feature = "macros")]
+pub use ci_group_macros::ci_group;
+#[cfg(feature = "std")]
+pub use commands::{
+    add_matcher, debug, error, install_panic_group, mask, notice, remove_matcher, set_env,
+    set_output, stop_commands, summary, warning, Annotation, CommandsGuard, Level,
+};
+#[cfg(feature = "std")]
+pub use custom::register_provider;
+pub use custom::{CiProvider, CustomProvider};
+#[cfg(feature = "tracing")]
+pub use layer::CiGroupLayer;
+#[cfg(feature = "std")]
+pub use std_impl::*;
 
 /// Represents a CI/CD provider (GitHub Actions, Azure DevOps, etc.).
+///
+/// New providers are added over time, so matches need a wildcard arm.
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
-enum Provider {
+#[non_exhaustive]
+pub enum Provider {
     GitHub,
     Azure,
+    GitLab,
+    /// CircleCI has no collapsible groups; titles are printed as plain banners.
+    CircleCI,
+    /// Buildkite sections have no end marker; the next section header closes the previous one.
+    Buildkite,
+    TeamCity,
+    /// Jenkins has no collapsible groups; titles are printed as header and footer banners.
+    Jenkins,
+    /// Bitbucket Pipelines has no collapsible groups; titles are printed as header and footer banners.
+    Bitbucket,
+    /// Drone has no collapsible groups; titles are printed as header and footer banners.
+    Drone,
+    /// Harness CI has no collapsible groups; titles are printed as header and footer banners.
+    Harness,
+    /// AppVeyor has no collapsible groups; titles are printed as header and footer banners.
+    AppVeyor,
+    /// Semaphore has no collapsible groups; titles are printed as header and footer banners.
+    Semaphore,
+    /// Some other CI that sets `CI` (Woodpecker, and many more); titles are printed as header and
+    /// footer banners.
+    Generic,
+    /// Not running in a supported CI; groups write nothing.
     None,
+    /// A provider added with [`register_provider`].
+    Custom(CustomProvider),
 }
-    
+
 impl Provider {
-    /// Detects the CI/CD provider from the environment variables.
-    fn detect() -> Self {
-        if std::env::var("GITHUB_ACTIONS")
-            .map(|v| v.eq_ignore_ascii_case("true"))
-            .unwrap_or(false)
-        {
-            Provider::GitHub
-        } else if std::env::var("TF_BUILD")
-            .map(|v| v.eq_ignore_ascii_case("true"))
-            .unwrap_or(false)
-        {
-            Provider::Azure
-        } else {
-            Provider::None
+    /// Parses a provider name as accepted by `CI_GROUP_PROVIDER` (case-insensitive).
+    #[cfg(any(feature = "std", feature = "serde"))]
+    fn from_name(name: &str) -> Option<Self> {
+        match name.to_ascii_lowercase().as_str() {
+            "github" => Some(Provider::GitHub),
+            "azure" => Some(Provider::Azure),
+            "gitlab" => Some(Provider::GitLab),
+            "circleci" => Some(Provider::CircleCI),
+            "buildkite" => Some(Provider::Buildkite),
+            "teamcity" => Some(Provider::TeamCity),
+            "jenkins" => Some(Provider::Jenkins),
+            "bitbucket" => Some(Provider::Bitbucket),
+            "drone" => Some(Provider::Drone),
+            "harness" => Some(Provider::Harness),
+            "appveyor" => Some(Provider::AppVeyor),
+            "semaphore" => Some(Provider::Semaphore),
+            "generic" => Some(Provider::Generic),
+            "none" => Some(Provider::None),
+            #[cfg(feature = "std")]
+            _ => CustomProvider::find(name).map(Provider::Custom),
+            #[cfg(not(feature = "std"))]
+            _ => None,
         }
     }
 
-    /// Returns true if the provider is active.
-    fn is_active(&self) -> bool {
-        !matches!(self, Provider::None)
+    /// Returns the provider's stable lowercase name, e.g. `"github"` or `"none"`.
+    ///
+    /// It's the name `CI_GROUP_PROVIDER` accepts and the one the `serde` feature writes, so it's
+    /// safe to log or store. A [registered](register_provider) provider returns its own name.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Provider::GitHub => "github",
+            Provider::Azure => "azure",
+            Provider::GitLab => "gitlab",
+            Provider::CircleCI => "circleci",
+            Provider::Buildkite => "buildkite",
+            Provider::TeamCity => "teamcity",
+            Provider::Jenkins => "jenkins",
+            Provider::Bitbucket => "bitbucket",
+            Provider::Drone => "drone",
+            Provider::Harness => "harness",
+            Provider::AppVeyor => "appveyor",
+            Provider::Semaphore => "semaphore",
+            Provider::Generic => "generic",
+            Provider::None => "none",
+            #[cfg(feature = "std")]
+            Provider::Custom(custom) => custom.name(),
+            #[cfg(not(feature = "std"))]
+            Provider::Custom(_) => "custom",
+        }
     }
 
-}
-
-
-/// A collapsible log group. Closes automatically when dropped.
-#[must_use = "group closes immediately when dropped. Bind it: let _g = open(...)"]
-pub struct Group {
-    provider: Provider,
-}
-
-impl Group {
-    /// Creates a new group with the given title.
-    pub fn new(title: &str) -> Self {
-        let provider = Provider::detect();
-
-        if provider.is_active() {
-            let mut stdout = std::io::stdout().lock();
-            let _ = match provider {
-                Provider::GitHub => writeln!(stdout, "\n::group::{title}"),
-                Provider::Azure => writeln!(stdout, "\n##[group]{title}"),
-                Provider::None => Ok(()),
-            };
-            let _ = stdout.flush();
-        }
+    /// Returns true for a real CI provider, false for [`Provider::None`].
+    pub fn is_active(&self) -> bool {
+        !matches!(self, Provider::None)
+    }
 
-        Group { provider }
+    /// Returns true if groups fold inside one another, false on GitHub Actions, where a group
+    /// opened inside another is [flattened](Group) by default.
+    pub fn supports_nesting(&self) -> bool {
+        !matches!(self, Provider::GitHub)
     }
-}
 
-impl Drop for Group {
-    fn drop(&mut self) {
-        if self.provider.is_active() {
-            let mut stdout = std::io::stdout().lock();
-            let _ = match self.provider {
-                Provider::GitHub => writeln!(stdout, "\n::endgroup::"),
-                Provider::Azure => writeln!(stdout, "\n##[endgroup]"),
-                Provider::None => Ok(()),
-            };
-            let _ = stdout.flush();
-        }
+    /// Returns true if groups collapse in the CI's log viewer, false for providers that only get
+    /// [banners](markers) and for [`Provider::None`].
+    pub fn supports_folding(&self) -> bool {
+        self.is_active() && !markers::uses_banners(*self)
     }
-}
 
-/// Opens a new log group. Alias for [`Group::new`].
-pub fn open(title: &str) -> Group {
-    Group::new(title)
+    /// Returns true if a group must write a marker to end, false for Buildkite, whose groups end at
+    /// the next header, and for [`Provider::None`].
+    pub fn needs_explicit_close(&self) -> bool {
+        !matches!(self, Provider::Buildkite | Provider::None)
+    }
 }
 
-/// Opens a log group for the duration of a block.
-///
-/// ```rust
-/// ci_group::group!("Build", {
-///     println!("Building...");
-/// });
-/// ```
-#[macro_export]
-macro_rules! group {
-    ($title:expr, $body:block) => {{
-        let _guard = $crate::open($title);
-        $body
-    }};
+/// Serialized as the lowercase `CI_GROUP_PROVIDER` name, e.g. `"github"`.
+#[cfg(feature = "serde")]
+impl serde::Serialize for Provider {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(self.name())
+    }
 }
 
+/// Accepts any name `CI_GROUP_PROVIDER` does, case-insensitively.
+#[cfg(feature = "serde")]
+impl<'de> serde::Deserialize<'de> for Provider {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct NameVisitor;
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-
-    #[test]
-    fn detects_github() {
-        temp_env::with_var("GITHUB_ACTIONS", Some("true"), || {
-            assert_eq!(Provider::detect(), Provider::GitHub);
-        });
-        temp_env::with_var_unset("GITHUB_ACTIONS", || {
-            assert_eq!(Provider::detect(), Provider::None);
-        });
-    }
-
-    #[test]
-    fn detects_github_case_insensitive() {
-        temp_env::with_var("GITHUB_ACTIONS", Some("TRUE"), || {
-            assert_eq!(Provider::detect(), Provider::GitHub);
-        });
-        temp_env::with_var("GITHUB_ACTIONS", Some("True"), || {
-            assert_eq!(Provider::detect(), Provider::GitHub);
-        });
-    }
+        impl serde::de::Visitor<'_> for NameVisitor {
+            type Value = Provider;
 
-    #[test]
-    fn rejects_non_true_values() {
-        temp_env::with_var("GITHUB_ACTIONS", Some("false"), || {
-            assert_eq!(Provider::detect(), Provider::None);
-        });
-        temp_env::with_var("GITHUB_ACTIONS", Some(""), || {
-            assert_eq!(Provider::detect(), Provider::None);
-        });
-        temp_env::with_var("GITHUB_ACTIONS", Some("1"), || {
-            assert_eq!(Provider::detect(), Provider::None);
-        });
-    }
+            fn expecting(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
+                f.write_str("a CI provider name such as \"github\"")
+            }
 
-    #[test]
-    fn detects_azure() {
-        // Must unset GITHUB_ACTIONS since this test runs in GitHub Actions
-        temp_env::with_vars(
-            [("TF_BUILD", Some("True")), ("GITHUB_ACTIONS", None)],
-            || {
-                assert_eq!(Provider::detect(), Provider::Azure);
-            },
-        );
-    }
+            fn visit_str<E: serde::de::Error>(self, name: &str) -> Result<Provider, E> {
+                Provider::from_name(name)
+                    .ok_or_else(|| E::invalid_value(serde::de::Unexpected::Str(name), &self))
+            }
+        }
 
-    #[test]
-    fn is_active_works() {
-        assert!(Provider::GitHub.is_active());
-        assert!(Provider::Azure.is_active());
-        assert!(!Provider::None.is_active());
+        deserializer.deserialize_str(NameVisitor)
     }
-
-}
\ No newline at end of file
+}
@@ -0,0 +1,653 @@
+//! Marker formatting without any I/O.
+//!
+//! These are the strings a [`Group`](crate::Group) writes, as pure functions of the provider and
+//! title. They only need `alloc`, so this module is available without the `std` feature.
+//!
+//! Titles are escaped so they can't pass for commands: [`encode_command_data`] on GitHub,
+//! [`azure_escape`] on Azure, [`teamcity_escape`] on TeamCity. Line breaks aren't touched; pass
+//! titles through [`sanitize_title`] first if they may contain any.
+//!
+//! ```rust
+//! use ci_group::{markers, Provider};
+//!
+//! assert_eq!(markers::open_marker(Provider::GitHub, "Build"), "\n::group::Build\n");
+//! assert_eq!(markers::close_marker(Provider::GitHub, "Build"), "\n::endgroup::\n");
+//! ```
+
+use alloc::format;
+use alloc::string::String;
+use core::time::Duration;
+
+use crate::Provider;
+
+/// The marker that opens a group titled `title`.
+///
+/// Empty for [`Provider::None`]. GitLab sections also carry a name and a Unix timestamp; this uses
+/// [`gitlab_slug`] of the title and `0`. Call [`gitlab_section_start`] to supply real ones.
+pub fn open_marker(provider: Provider, title: &str) -> String {
+    open_marker_with(provider, title, false, false)
+}
+
+/// Like [`open_marker`], but for a group requested expanded, and with `color` printing banners in
+/// bold.
+///
+/// Expanded groups are a plain banner on GitHub, which can't start a group open, `##[section]` on
+/// Azure and `+++` on Buildkite. Other providers ignore `expanded`.
+pub(crate) fn open_marker_with(
+    provider: Provider,
+    title: &str,
+    expanded: bool,
+    color: bool,
+) -> String {
+    if let Some(banner) = banner_with(provider, expanded) {
+        return banner.open(title, color);
+    }
+    match provider {
+        #[cfg(feature = "std")]
+        Provider::Custom(custom) => custom.open_marker(title),
+        Provider::Azure if expanded => format!("\n##[section]{}\n", azure_escape(title)),
+        Provider::GitHub => format!("\n::group::{}\n", encode_command_data(title)),
+        Provider::Azure => format!("\n##[group]{}\n", azure_escape(title)),
+        Provider::GitLab => gitlab_section_start(&gitlab_slug(title), 0, title),
+        Provider::Buildkite => {
+            let header = if expanded { "+++" } else { "---" };
+            format!("\n{header} {title}\n")
+        }
+        Provider::TeamCity => format!(
+            "\n##teamcity[blockOpened name='{}']\n",
+            teamcity_escape(title)
+        ),
+        _ => String::new(),
+    }
+}
+
+/// The marker that closes a group titled `title`.
+///
+/// Empty for [`Provider::None`] and for Buildkite, whose groups end at the next header. For GitLab
+/// see [`open_marker`]; [`gitlab_section_end`] takes the real name and timestamp.
+pub fn close_marker(provider: Provider, title: &str) -> String {
+    close_marker_with(provider, title, false, false)
+}
+
+/// Like [`close_marker`], but for a group opened by [`open_marker_with`] with `expanded`, and with
+/// `color` printing banners dimmed.
+///
+/// An Azure `##[section]` is a single line and has nothing to close.
+pub(crate) fn close_marker_with(
+    provider: Provider,
+    title: &str,
+    expanded: bool,
+    color: bool,
+) -> String {
+    if !provider.needs_explicit_close() {
+        return String::new();
+    }
+    if let Some(banner) = banner_with(provider, expanded) {
+        return banner.close(title, color);
+    }
+    match provider {
+        #[cfg(feature = "std")]
+        Provider::Custom(custom) => custom.close_marker(title),
+        Provider::Azure if expanded => String::new(),
+        Provider::GitHub => String::from("\n::endgroup::\n"),
+        Provider::Azure => String::from("\n##[endgroup]\n"),
+        Provider::GitLab => gitlab_section_end(&gitlab_slug(title), 0),
+        Provider::TeamCity => format!(
+            "\n##teamcity[blockClosed name='{}']\n",
+            teamcity_escape(title)
+        ),
+        _ => String::new(),
+    }
+}
+
+/// The line a [timed](crate::Group::timed) group prints after its close marker.
+///
+/// The title is escaped for the provider that reads the line. On Azure, commands are defused as in
+/// [`azure_escape`]. On GitHub, where the title starts the line, one that looks like a command
+/// (`::endgroup::`) has its leading run of `:` shortened to one, and the runner's legacy
+/// `##[command]` syntax is defused the same way as on Azure. On TeamCity, `##teamcity[` is
+/// shortened to `#teamcity[`. Other providers run no commands from the log, so titles are kept.
+pub fn timing_line(provider: Provider, title: &str, elapsed: Duration) -> String {
+    let secs = elapsed.as_secs_f64();
+    match provider {
+        Provider::Azure => format!("##[command]{} took {secs:.1}s\n", azure_escape(title)),
+        Provider::GitHub => {
+            let title = azure_escape(title);
+            match title.strip_prefix("::") {
+                Some(rest) => format!(":{} took {secs:.1}s\n", rest.trim_start_matches(':')),
+                None => format!("{title} took {secs:.1}s\n"),
+            }
+        }
+        Provider::TeamCity => {
+            let mut title = String::from(title);
+            // `###teamcity[` would shrink to a match again.
+            while title.contains("##teamcity[") {
+                title = title.replace("##teamcity[", "#teamcity[");
+            }
+            format!("{title} took {secs:.1}s\n")
+        }
+        _ => format!("{title} took {secs:.1}s\n"),
+    }
+}
+
+/// A [`Mode::JsonEvents`](crate::Mode::JsonEvents) line: `event` is `open` or `close`,
+/// `timestamp_ms` the Unix time in milliseconds, and `depth` 1 for an outermost group.
+///
+/// ```rust
+/// assert_eq!(
+///     ci_group::markers::json_event("open", "Build", 1700000000000, 1),
+///     "{\"event\":\"open\",\"title\":\"Build\",\"ts\":1700000000000,\"depth\":1}\n"
+/// );
+/// ```
+pub fn json_event(event: &str, title: &str, timestamp_ms: u64, depth: usize) -> String {
+    let event = json_string(event);
+    let title = json_string(title);
+    format!("{{\"event\":{event},\"title\":{title},\"ts\":{timestamp_ms},\"depth\":{depth}}}\n")
+}
+
+/// Quotes `value` as a JSON string.
+fn json_string(value: &str) -> String {
+    let mut quoted = String::with_capacity(value.len() + 2);
+    quoted.push('"');
+    for c in value.chars() {
+        match c {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            '\n' => quoted.push_str("\\n"),
+            '\r' => quoted.push_str("\\r"),
+            '\t' => quoted.push_str("\\t"),
+            c if u32::from(c) < 0x20 => quoted.push_str(&format!("\\u{:04x}", u32::from(c))),
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+/// Opens a GitLab collapsible section. `name` must be unique within the job and match `[0-9a-zA-Z_]`.
+///
+/// The title is escaped as in [`gitlab_escape`].
+pub fn gitlab_section_start(name: &str, timestamp: u64, title: &str) -> String {
+    let title = gitlab_escape(title);
+    format!("\n\x1b[0Ksection_start:{timestamp}:{name}\r\x1b[0K{title}\n")
+}
+
+/// Defuses the `\x1b[0K` that starts GitLab's section markers, so a title can't open or close
+/// sections of its own. Other escape sequences, such as colours, are kept.
+pub fn gitlab_escape(title: &str) -> String {
+    title.replace("\x1b[0K", " [0K")
+}
+
+/// Closes the GitLab section opened with the same `name`.
+pub fn gitlab_section_end(name: &str, timestamp: u64) -> String {
+    format!("\n\x1b[0Ksection_end:{timestamp}:{name}\r\x1b[0K\n")
+}
+
+/// Turns a title into a GitLab section name: characters outside `[0-9a-zA-Z_]` become `_`.
+pub fn gitlab_slug(title: &str) -> String {
+    title
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+        .collect()
+}
+
+/// Replaces line breaks with spaces so a title can't end its marker line early.
+pub fn sanitize_title(title: &str) -> String {
+    title.replace("\r\n", " ").replace(['\r', '\n'], " ")
+}
+
+/// Cuts `title` to at most `max_len` bytes, on a character boundary, ending in `…` if anything
+/// was cut. The `…` counts towards `max_len`; below its 3 bytes the title is only cut.
+pub fn truncate_title(title: &str, max_len: usize) -> String {
+    if title.len() <= max_len {
+        return String::from(title);
+    }
+    let ellipsis = if max_len >= '…'.len_utf8() {
+        "…"
+    } else {
+        ""
+    };
+    let mut end = max_len - ellipsis.len();
+    while !title.is_char_boundary(end) {
+        end -= 1;
+    }
+    format!("{}{ellipsis}", &title[..end])
+}
+
+/// Escapes a TeamCity service message attribute value using its `|` scheme.
+pub fn teamcity_escape(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        match c {
+            '|' => escaped.push_str("||"),
+            '\'' => escaped.push_str("|'"),
+            '[' => escaped.push_str("|["),
+            ']' => escaped.push_str("|]"),
+            '\n' => escaped.push_str("|n"),
+            '\r' => escaped.push_str("|r"),
+            '\u{85}' => escaped.push_str("|x"),
+            '\u{2028}' => escaped.push_str("|l"),
+            '\u{2029}' => escaped.push_str("|p"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// Defuses Azure logging commands inside a title by shrinking the `#`s before `[` or `vso[` to one.
+///
+/// `My ##[endgroup] step` becomes `My #[endgroup] step`, which the agent prints as text.
+pub fn azure_escape(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    let mut rest = value;
+    while let Some(start) = rest.find("##") {
+        let hashes = rest[start..].len() - rest[start..].trim_start_matches('#').len();
+        let after = &rest[start + hashes..];
+        escaped.push_str(&rest[..start]);
+        if after.starts_with('[') || after.starts_with("vso[") {
+            escaped.push('#');
+        } else {
+            escaped.push_str(&rest[start..start + hashes]);
+        }
+        rest = after;
+    }
+    escaped.push_str(rest);
+    escaped
+}
+
+/// Escapes a GitHub workflow command message so it can't end the command early.
+pub fn encode_command_data(value: &str) -> String {
+    value
+        .replace('%', "%25")
+        .replace('\r', "%0D")
+        .replace('\n', "%0A")
+}
+
+/// Escapes a GitHub workflow command property value, which additionally can't contain `:` or `,`.
+pub fn encode_property(value: &str) -> String {
+    encode_command_data(value)
+        .replace(':', "%3A")
+        .replace(',', "%2C")
+}
+
+/// Undoes [`encode_command_data`], reading `%xx` sequences left to right as the runner does.
+#[cfg(test)]
+fn decode_command_data(value: &str) -> String {
+    percent_decode(value, &[("%25", '%'), ("%0D", '\r'), ("%0A", '\n')])
+}
+
+/// Undoes [`encode_property`].
+#[cfg(test)]
+fn decode_property(value: &str) -> String {
+    percent_decode(
+        value,
+        &[
+            ("%25", '%'),
+            ("%0D", '\r'),
+            ("%0A", '\n'),
+            ("%3A", ':'),
+            ("%2C", ','),
+        ],
+    )
+}
+
+/// Replaces each escape in `table` with its character; other `%`s are kept as they are.
+#[cfg(test)]
+fn percent_decode(value: &str, table: &[(&str, char)]) -> String {
+    let mut decoded = String::with_capacity(value.len());
+    let mut rest = value;
+    while let Some(start) = rest.find('%') {
+        decoded.push_str(&rest[..start]);
+        rest = &rest[start..];
+        match table.iter().find(|(escape, _)| rest.starts_with(escape)) {
+            Some(&(escape, c)) => {
+                decoded.push(c);
+                rest = &rest[escape.len()..];
+            }
+            None => {
+                decoded.push('%');
+                rest = &rest[1..];
+            }
+        }
+    }
+    decoded.push_str(rest);
+    decoded
+}
+
+/// Plain-text group boundaries, so output is at least visually segmented where it can't be folded.
+#[derive(Debug, Clone, Copy)]
+struct Banner {
+    rule: &'static str,
+    /// Print a titled footer on close; otherwise just a blank separator line.
+    footer: bool,
+}
+
+impl Banner {
+    /// The header, in bold with `color`.
+    fn open(self, title: &str, color: bool) -> String {
+        let rule = self.rule;
+        let line = paint(&format!("{rule} {title} {rule}"), "1", color);
+        format!("\n{line}\n")
+    }
+
+    /// The footer, dimmed with `color`.
+    fn close(self, title: &str, color: bool) -> String {
+        let rule = self.rule;
+        if self.footer {
+            let line = paint(&format!("{rule} end {title} {rule}"), "2", color);
+            format!("\n{line}\n")
+        } else {
+            String::from("\n")
+        }
+    }
+}
+
+/// Wraps `text` in the SGR escape `code` and a reset, if `color`.
+fn paint(text: &str, code: &str, color: bool) -> String {
+    if color {
+        format!("\x1b[{code}m{text}\x1b[0m")
+    } else {
+        String::from(text)
+    }
+}
+
+/// Returns true if `provider` prints banners instead of folding groups.
+pub(crate) fn uses_banners(provider: Provider) -> bool {
+    banner(provider).is_some()
+}
+
+/// Like [`banner`], but also for a GitHub group requested expanded.
+fn banner_with(provider: Provider, expanded: bool) -> Option<Banner> {
+    match provider {
+        Provider::GitHub if expanded => Some(RULED),
+        _ => banner(provider),
+    }
+}
+
+/// The banner for most providers that can't fold output.
+const RULED: Banner = Banner {
+    rule: "==========",
+    footer: true,
+};
+
+/// The banner style for providers that can't fold output, or `None` for native groups.
+fn banner(provider: Provider) -> Option<Banner> {
+    match provider {
+        Provider::CircleCI => Some(Banner {
+            rule: "-----",
+            footer: false,
+        }),
+        Provider::Jenkins
+        | Provider::Bitbucket
+        | Provider::Drone
+        | Provider::Harness
+        | Provider::AppVeyor
+        | Provider::Semaphore
+        | Provider::Generic => Some(RULED),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn formats_native_groups() {
+        assert_eq!(open_marker(Provider::GitHub, "Build"), "\n::group::Build\n");
+        assert_eq!(close_marker(Provider::GitHub, "Build"), "\n::endgroup::\n");
+        assert_eq!(open_marker(Provider::Azure, "Build"), "\n##[group]Build\n");
+        assert_eq!(close_marker(Provider::Azure, "Build"), "\n##[endgroup]\n");
+    }
+
+    #[test]
+    fn formats_gitlab_sections() {
+        assert_eq!(
+            gitlab_section_start("unit_1", 1700000000, "Unit tests"),
+            "\n\x1b[0Ksection_start:1700000000:unit_1\r\x1b[0KUnit tests\n"
+        );
+        assert_eq!(
+            gitlab_section_end("unit_1", 1700000005),
+            "\n\x1b[0Ksection_end:1700000005:unit_1\r\x1b[0K\n"
+        );
+        assert_eq!(
+            open_marker(Provider::GitLab, "Unit tests"),
+            "\n\x1b[0Ksection_start:0:Unit_tests\r\x1b[0KUnit tests\n"
+        );
+        assert_eq!(gitlab_slug("Build & Test: api"), "Build___Test__api");
+    }
+
+    #[test]
+    fn gitlab_titles_cannot_end_sections() {
+        assert_eq!(
+            gitlab_section_start("x_1", 0, "\x1b[0Ksection_end:1:x \x1b[1mbold\x1b[0m"),
+            "\n\x1b[0Ksection_start:0:x_1\r\x1b[0K [0Ksection_end:1:x \x1b[1mbold\x1b[0m\n"
+        );
+    }
+
+    #[test]
+    fn formats_buildkite_headers() {
+        assert_eq!(open_marker(Provider::Buildkite, "Build"), "\n--- Build\n");
+        assert_eq!(
+            open_marker_with(Provider::Buildkite, "Build", true, false),
+            "\n+++ Build\n"
+        );
+        assert_eq!(close_marker(Provider::Buildkite, "Build"), "");
+    }
+
+    #[test]
+    fn formats_expanded_groups() {
+        assert_eq!(
+            open_marker_with(Provider::Azure, "Build", true, false),
+            "\n##[section]Build\n"
+        );
+        assert_eq!(close_marker_with(Provider::Azure, "Build", true, false), "");
+        assert_eq!(
+            open_marker_with(Provider::GitHub, "Build", true, false),
+            "\n========== Build ==========\n"
+        );
+        assert_eq!(
+            close_marker_with(Provider::GitHub, "Build", true, false),
+            "\n========== end Build ==========\n"
+        );
+        assert_eq!(
+            open_marker_with(Provider::GitLab, "Build", true, false),
+            open_marker(Provider::GitLab, "Build")
+        );
+    }
+
+    #[test]
+    fn formats_banners() {
+        assert_eq!(
+            open_marker(Provider::CircleCI, "Build"),
+            "\n----- Build -----\n"
+        );
+        assert_eq!(close_marker(Provider::CircleCI, "Build"), "\n");
+        assert_eq!(
+            close_marker(Provider::Jenkins, "Build"),
+            "\n========== end Build ==========\n"
+        );
+    }
+
+    #[test]
+    fn colors_banners_only() {
+        assert_eq!(
+            open_marker_with(Provider::Jenkins, "Build", false, true),
+            "\n\x1b[1m========== Build ==========\x1b[0m\n"
+        );
+        assert_eq!(
+            close_marker_with(Provider::Jenkins, "Build", false, true),
+            "\n\x1b[2m========== end Build ==========\x1b[0m\n"
+        );
+        assert_eq!(
+            close_marker_with(Provider::CircleCI, "Build", false, true),
+            "\n"
+        );
+        assert_eq!(
+            open_marker_with(Provider::GitHub, "Build", false, true),
+            "\n::group::Build\n"
+        );
+    }
+
+    #[test]
+    fn none_writes_nothing() {
+        assert_eq!(open_marker(Provider::None, "Build"), "");
+        assert_eq!(close_marker(Provider::None, "Build"), "");
+    }
+
+    #[test]
+    fn formats_timing_lines() {
+        let elapsed = Duration::from_millis(3200);
+        assert_eq!(
+            timing_line(Provider::GitHub, "Build", elapsed),
+            "Build took 3.2s\n"
+        );
+        assert_eq!(
+            timing_line(Provider::Azure, "Build", elapsed),
+            "##[command]Build took 3.2s\n"
+        );
+    }
+
+    #[test]
+    fn timing_lines_cannot_start_commands() {
+        let elapsed = Duration::from_secs(1);
+        assert_eq!(
+            timing_line(Provider::GitHub, "::endgroup::", elapsed),
+            ":endgroup:: took 1.0s\n"
+        );
+        assert_eq!(
+            timing_line(Provider::GitHub, "##[endgroup]", elapsed),
+            "#[endgroup] took 1.0s\n"
+        );
+        assert_eq!(
+            timing_line(Provider::Azure, "##[endgroup]", elapsed),
+            "##[command]#[endgroup] took 1.0s\n"
+        );
+        assert_eq!(
+            timing_line(
+                Provider::TeamCity,
+                "##teamcity[blockClosed name='x']",
+                elapsed
+            ),
+            "#teamcity[blockClosed name='x'] took 1.0s\n"
+        );
+        assert_eq!(
+            timing_line(Provider::TeamCity, "###teamcity[", elapsed),
+            "#teamcity[ took 1.0s\n"
+        );
+        assert_eq!(
+            timing_line(Provider::GitHub, "::::x", elapsed),
+            ":x took 1.0s\n"
+        );
+        assert_eq!(
+            timing_line(Provider::GitHub, "a::b", elapsed),
+            "a::b took 1.0s\n"
+        );
+    }
+
+    #[test]
+    fn timing_lines_only_escape_for_their_provider() {
+        let elapsed = Duration::from_secs(1);
+        for provider in [Provider::Jenkins, Provider::GitLab, Provider::TeamCity] {
+            assert_eq!(
+                timing_line(provider, "##[x] 100%", elapsed),
+                "##[x] 100% took 1.0s\n"
+            );
+        }
+        assert_eq!(
+            timing_line(Provider::Jenkins, "::endgroup::", elapsed),
+            "::endgroup:: took 1.0s\n"
+        );
+    }
+
+    #[test]
+    fn sanitizes_line_breaks_in_titles() {
+        assert_eq!(sanitize_title("line1\nline2"), "line1 line2");
+        assert_eq!(sanitize_title("a\r\nb\rc"), "a b c");
+        assert_eq!(sanitize_title("plain"), "plain");
+    }
+
+    #[test]
+    fn truncates_titles_at_char_boundaries() {
+        assert_eq!(truncate_title("short", 5), "short");
+        assert_eq!(truncate_title("exactly", 6), "exa…");
+        assert_eq!(truncate_title("exactly", 3), "…");
+        // "é" is two bytes; cutting at 3 would split the second one.
+        assert_eq!(truncate_title("éééé", 6), "é…");
+        assert_eq!(truncate_title("ééé", 2), "é");
+        assert_eq!(truncate_title("é", 1), "");
+    }
+
+    #[test]
+    fn escapes_teamcity_values() {
+        assert_eq!(teamcity_escape("it's [a|b]\r\n"), "it|'s |[a||b|]|r|n");
+        assert_eq!(teamcity_escape("plain"), "plain");
+        assert_eq!(
+            open_marker(Provider::TeamCity, "a'b"),
+            "\n##teamcity[blockOpened name='a|'b']\n"
+        );
+    }
+
+    #[test]
+    fn escapes_azure_commands_in_titles() {
+        assert_eq!(azure_escape("##[endgroup]"), "#[endgroup]");
+        assert_eq!(
+            azure_escape("a ##vso[task.complete] b ##[error]"),
+            "a #vso[task.complete] b #[error]"
+        );
+        assert_eq!(azure_escape("###[x] #1 ## plain"), "#[x] #1 ## plain");
+        assert_eq!(
+            open_marker(Provider::Azure, "##[endgroup]x"),
+            "\n##[group]#[endgroup]x\n"
+        );
+    }
+
+    #[test]
+    fn encodes_github_titles() {
+        assert_eq!(
+            open_marker(Provider::GitHub, "100%0A done"),
+            "\n::group::100%250A done\n"
+        );
+    }
+
+    #[test]
+    fn json_events_escape_titles() {
+        assert_eq!(
+            json_event("close", "a \"b\" \\ c\u{1}", 5, 2),
+            "{\"event\":\"close\",\"title\":\"a \\\"b\\\" \\\\ c\\u0001\",\"ts\":5,\"depth\":2}\n"
+        );
+    }
+
+    #[test]
+    fn escapes_command_data_and_properties() {
+        assert_eq!(encode_command_data("100%\r\n"), "100%25%0D%0A");
+        assert_eq!(encode_property("a:b,c"), "a%3Ab%2Cc");
+        assert_eq!(decode_command_data("100%250A%0A"), "100%0A\n");
+        assert_eq!(decode_property("a%3Ab%2Cc%2"), "a:b,c%2");
+    }
+
+    /// Arbitrary text, weighted towards `%`, line breaks, `:`, `,` and what they encode to.
+    fn command_text() -> impl proptest::strategy::Strategy<Value = String> {
+        proptest::prop_oneof![
+            "[a-z %:,\r\n]{0,40}",
+            "(%|%25|%0A|%0D|%3A|%2C|:|,|\r|\n|x){0,12}",
+            proptest::arbitrary::any::<String>(),
+        ]
+    }
+
+    proptest::proptest! {
+        #[test]
+        fn command_data_round_trips(value in command_text()) {
+            let encoded = encode_command_data(&value);
+            proptest::prop_assert!(!encoded.contains(['\r', '\n']), "{encoded:?}");
+            proptest::prop_assert_eq!(decode_command_data(&encoded), value);
+        }
+
+        #[test]
+        fn properties_round_trip(value in command_text()) {
+            let encoded = encode_property(&value);
+            proptest::prop_assert!(!encoded.contains(['\r', '\n', ':', ',']), "{encoded:?}");
+            proptest::prop_assert_eq!(decode_property(&encoded), value);
+        }
+    }
+}
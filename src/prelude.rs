@@ -0,0 +1,25 @@
+//! The items most programs need, for a single glob import.
+//!
+//! ```rust
+//! use ci_group::prelude::*;
+//!
+//! let n = group!("Build", {
+//!     glog!("compiling");
+//!     warning("deprecated config key");
+//!     3
+//! });
+//! let _g = GroupBuilder::new().title("Test").open();
+//! if detected_provider() == Provider::GitHub {
+//!     notice("ran on GitHub Actions");
+//! }
+//! ```
+//!
+//! Everything here is also at the crate root. Less common APIs, such as output redirection and
+//! workflow commands beyond annotations, stay out of it so the glob doesn't shadow your own names.
+
+pub use crate::Provider;
+#[cfg(feature = "std")]
+pub use crate::{
+    detected_provider, error, glog, group, notice, open, open_with, warning, Group, GroupBuilder,
+    Mode,
+};
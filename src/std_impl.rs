@@ -0,0 +1,5455 @@
+//! Everything that needs `std`: detection, groups, output and the process-wide settings.
+
+use std::borrow::Cow;
+use std::cell::RefCell;
+use std::collections::VecDeque;
+use std::io::{IsTerminal, Write};
+use std::marker::PhantomData;
+use std::process::{Command, ExitStatus};
+use std::sync::atomic::{AtomicBool, AtomicU8, AtomicUsize, Ordering};
+use std::sync::{Arc, Condvar, Mutex, MutexGuard};
+use std::thread::{self, ThreadId};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use crate::commands::append_to_env_file;
+use crate::markers::{self, sanitize_title};
+use crate::{error, Annotation, CustomProvider, Provider};
+
+impl Provider {
+    /// Detects the CI/CD provider from the environment variables.
+    ///
+    /// `CI_GROUP_PROVIDER` takes precedence when it names a known provider; unrecognized values are ignored.
+    /// A truthy `CI_GROUP_DISABLE` overrides both and returns [`Provider::None`].
+    /// Unlike [`detected_provider`], this reads the environment on every call and caches nothing.
+    pub fn detect_from_env() -> Self {
+        if env_is_truthy("CI_GROUP_DISABLE") {
+            return Provider::None;
+        }
+        if let Some(provider) = std::env::var("CI_GROUP_PROVIDER")
+            .ok()
+            .and_then(|v| Provider::from_name(&v))
+        {
+            return provider;
+        }
+        if let Some(custom) = CustomProvider::detect() {
+            return Provider::Custom(custom);
+        }
+        DETECTION_ORDER
+            .iter()
+            .find(|(_, check)| check.matches())
+            .map_or(Provider::None, |&(provider, _)| provider)
+    }
+
+    /// Former name of [`detect_from_env`](Self::detect_from_env).
+    #[deprecated(note = "renamed to `Provider::detect_from_env`")]
+    pub fn detect() -> Self {
+        Self::detect_from_env()
+    }
+
+    /// The provider for this process, detected on first use and then cached.
+    ///
+    /// Detection reads several environment variables; caching means thousands of short groups cost
+    /// one lookup pass instead of one per group, and a mid-run env change can't flip the format.
+    pub(crate) fn current() -> Self {
+        *DETECTED
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .get_or_insert_with(Provider::detect_from_env)
+    }
+
+    /// Starts a group titled `title`: the marker that opens it, and what ending it takes.
+    ///
+    /// Pass the result to [`close_marker`](Self::close_marker) for the end marker. Nothing is
+    /// recorded, so a marker that's only looked at needs no closing. As with [`Group`], GitLab
+    /// sections get a unique name and the current time; [`markers::open_marker`] has fixed ones.
+    ///
+    /// ```rust
+    /// use ci_group::Provider;
+    ///
+    /// let open = Provider::Azure.open_marker("Build");
+    /// assert_eq!(open.marker(), Some("\n##[group]Build\n"));
+    /// assert_eq!(Provider::Azure.close_marker(open).as_deref(), Some("\n##[endgroup]\n"));
+    /// assert_eq!(Provider::None.open_marker("Build").marker(), None);
+    /// ```
+    pub fn open_marker(&self, title: &str) -> OpenMarker {
+        let (marker, section) = self.open_group(title, false, false);
+        OpenMarker {
+            marker,
+            title: title.to_owned(),
+            section,
+        }
+    }
+
+    /// The marker that ends the group `open` started, or `None` if this provider's groups end
+    /// without one.
+    pub fn close_marker(&self, open: OpenMarker) -> Option<String> {
+        Some(self.close_group(&open.title, open.section.as_deref(), false, false))
+            .filter(|marker| !marker.is_empty())
+    }
+}
+
+/// A group started by [`Provider::open_marker`], to hand to [`Provider::close_marker`].
+///
+/// Holds what the end marker must repeat: the title, and GitLab's unique section name.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[must_use = "pass it to `Provider::close_marker` to end the group"]
+pub struct OpenMarker {
+    marker: String,
+    title: String,
+    section: Option<String>,
+}
+
+impl OpenMarker {
+    /// The marker that opens the group, or `None` if this provider writes none.
+    pub fn marker(&self) -> Option<&str> {
+        Some(self.marker.as_str()).filter(|marker| !marker.is_empty())
+    }
+}
+
+/// How a provider announces itself in the environment.
+#[derive(Debug, Clone, Copy)]
+enum EnvCheck {
+    /// The variable is `true` (case-insensitive).
+    True(&'static str),
+    /// The variable is set at all, even to an empty string.
+    Set(&'static str),
+    /// The variable is set to anything non-empty: a version, a URL, a build number.
+    NonEmpty(&'static str),
+    /// The variable is set to anything but an empty string, `false` or `0`.
+    Truthy(&'static str),
+    /// Any of the checks matches, tried in order.
+    Any(&'static [EnvCheck]),
+}
+
+impl EnvCheck {
+    fn matches(self) -> bool {
+        match self {
+            EnvCheck::True(name) => env_is_true(name),
+            EnvCheck::Set(name) => env_is_set(name),
+            EnvCheck::NonEmpty(name) => env_is_non_empty(name),
+            EnvCheck::Truthy(name) => env_is_truthy(name),
+            EnvCheck::Any(checks) => checks.iter().any(|check| check.matches()),
+        }
+    }
+}
+
+/// Providers in detection order; the first whose check matches wins.
+///
+/// Providers that set a dedicated `true` flag come before those detected by presence, since a
+/// generic variable like `JENKINS_URL` can leak into a job run by another CI. GitHub Actions leads
+/// because its runners are often hosted inside other orchestrators, and its markers are what the
+/// visible log understands. The generic `CI` variable, which most providers set too, comes last.
+const DETECTION_ORDER: &[(Provider, EnvCheck)] = &[
+    (Provider::GitHub, EnvCheck::True("GITHUB_ACTIONS")),
+    // Some self-hosted agents don't set `TF_BUILD`, but the collection URI is Azure's own.
+    (
+        Provider::Azure,
+        EnvCheck::Any(&[
+            EnvCheck::True("TF_BUILD"),
+            EnvCheck::NonEmpty("SYSTEM_TEAMFOUNDATIONCOLLECTIONURI"),
+        ]),
+    ),
+    (Provider::GitLab, EnvCheck::True("GITLAB_CI")),
+    (Provider::CircleCI, EnvCheck::True("CIRCLECI")),
+    (Provider::Buildkite, EnvCheck::True("BUILDKITE")),
+    (Provider::Drone, EnvCheck::True("DRONE")),
+    (Provider::AppVeyor, EnvCheck::True("APPVEYOR")),
+    (Provider::Semaphore, EnvCheck::True("SEMAPHORE")),
+    (Provider::TeamCity, EnvCheck::Set("TEAMCITY_VERSION")),
+    (Provider::Jenkins, EnvCheck::NonEmpty("JENKINS_URL")),
+    (
+        Provider::Bitbucket,
+        EnvCheck::NonEmpty("BITBUCKET_BUILD_NUMBER"),
+    ),
+    (Provider::Harness, EnvCheck::NonEmpty("HARNESS_BUILD_ID")),
+    (Provider::Generic, EnvCheck::Truthy("CI")),
+];
+
+/// The provider groups and annotations write for, detected from the environment on first use.
+///
+/// Honours `CI_GROUP_PROVIDER`, and is cached like the rest of the crate's detection, so it always
+/// agrees with what [`open`] writes in [`Mode::Auto`].
+///
+/// ```rust
+/// use ci_group::{detected_provider, Provider};
+///
+/// if detected_provider() == Provider::GitHub {
+///     println!("::notice::running on GitHub Actions");
+/// }
+/// if !detected_provider().is_active() {
+///     // Skip building annotations nobody will see.
+/// }
+/// ```
+pub fn detected_provider() -> Provider {
+    Provider::current()
+}
+
+/// Returns true if groups on the [detected provider](detected_provider) collapse in the log
+/// viewer, so output inside them can afford to be verbose.
+///
+/// Unlike [`Provider::is_active`], this is false on CIs where groups are only banners around the
+/// output, such as Jenkins or CircleCI, and for GitHub workflows run locally by
+/// [act](https://github.com/nektos/act) (`ACT` set), where groups are written as banners too.
+/// See [`Provider::supports_folding`].
+///
+/// ```rust
+/// let verbose = ci_group::supports_folding();
+/// ```
+pub fn supports_folding() -> bool {
+    let provider = detected_provider();
+    provider.supports_folding() && !(provider == Provider::GitHub && running_under_act())
+}
+
+static DETECTED: Mutex<Option<Provider>> = Mutex::new(None);
+
+/// Forgets the cached provider so the next group detects it again.
+///
+/// For tests that change CI variables between cases; production code detects once per process.
+/// Available with the `test-util` feature.
+///
+/// ```rust
+/// # #[cfg(feature = "test-util")] {
+/// std::env::set_var("CI_GROUP_PROVIDER", "azure");
+/// ci_group::reset_provider_cache();
+/// assert_eq!(ci_group::detected_provider(), ci_group::Provider::Azure);
+/// # }
+/// ```
+#[cfg(any(test, feature = "test-util"))]
+pub fn reset_provider_cache() {
+    forget_detected_provider();
+}
+
+pub(crate) fn forget_detected_provider() {
+    *DETECTED.lock().unwrap_or_else(|e| e.into_inner()) = None;
+    UNDER_ACT.store(0, Ordering::Relaxed);
+    DISABLED_BY_ENV.store(0, Ordering::Relaxed);
+}
+
+/// Whether `ACT` is set: 0 until checked, then 1 for no and 2 for yes. Cached like [`DETECTED`].
+static UNDER_ACT: AtomicU8 = AtomicU8::new(0);
+
+/// Whether `CI_GROUP_DISABLE` is set, cached like [`UNDER_ACT`].
+static DISABLED_BY_ENV: AtomicU8 = AtomicU8::new(0);
+
+/// [`env_is_truthy`], read once and then kept in `cache` until [`forget_detected_provider`].
+fn cached_env_flag(cache: &AtomicU8, name: &str) -> bool {
+    match cache.load(Ordering::Relaxed) {
+        0 => {
+            let set = env_is_truthy(name);
+            cache.store(1 + u8::from(set), Ordering::Relaxed);
+            set
+        }
+        state => state == 2,
+    }
+}
+
+/// Returns true under [act](https://github.com/nektos/act), which runs GitHub workflows locally but
+/// doesn't reliably fold groups. GitHub groups are written as banners there.
+fn running_under_act() -> bool {
+    cached_env_flag(&UNDER_ACT, "ACT")
+}
+
+/// Returns true if `CI_GROUP_DISABLE` turns every group into a no-op, whatever the mode.
+fn disabled_by_env() -> bool {
+    cached_env_flag(&DISABLED_BY_ENV, "CI_GROUP_DISABLE")
+}
+
+/// Returns true if the environment variable is set to `true` (case-insensitive).
+fn env_is_true(name: &str) -> bool {
+    std::env::var(name)
+        .map(|v| v.eq_ignore_ascii_case("true"))
+        .unwrap_or(false)
+}
+
+/// Returns true if the environment variable is set, even to an empty string.
+fn env_is_set(name: &str) -> bool {
+    std::env::var_os(name).is_some()
+}
+
+/// Returns true if the environment variable is set to a non-empty value.
+///
+/// For providers that export an identifier (a version, a URL) rather than `true`.
+fn env_is_non_empty(name: &str) -> bool {
+    std::env::var_os(name).is_some_and(|v| !v.is_empty())
+}
+
+/// Returns true if the environment variable is set to anything but an empty string, `false` or `0`.
+fn env_is_truthy(name: &str) -> bool {
+    std::env::var_os(name)
+        .is_some_and(|v| !(v.is_empty() || v == "0" || v.eq_ignore_ascii_case("false")))
+}
+
+/// Set while [`capture`] runs, so markers carry no timestamps or durations.
+static FROZEN_CLOCK: AtomicBool = AtomicBool::new(false);
+
+fn clock_frozen() -> bool {
+    cfg!(any(test, feature = "test-util")) && FROZEN_CLOCK.load(Ordering::Relaxed)
+}
+
+/// Current Unix time in seconds, as GitLab expects in section markers.
+fn unix_now() -> u64 {
+    if clock_frozen() {
+        return 0;
+    }
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+/// Current Unix time in milliseconds, for [`Mode::JsonEvents`].
+fn unix_now_millis() -> u64 {
+    if clock_frozen() {
+        return 0;
+    }
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or(0)
+}
+
+/// Appends `[unix time] event` to the file named by `CI_GROUP_LOG`, if set, ignoring every failure.
+///
+/// A record of every group for post-mortem debugging, independent of the markers themselves.
+fn mirror_to_log(event: std::fmt::Arguments) {
+    if std::env::var_os("CI_GROUP_LOG").is_some() {
+        append_to_env_file("CI_GROUP_LOG", &format!("[{}] {event}\n", unix_now()));
+    }
+}
+
+static NEXT_SECTION: AtomicUsize = AtomicUsize::new(0);
+
+/// Builds a unique GitLab section name from a title.
+///
+/// GitLab only accepts `[0-9a-zA-Z_]`, so other characters become `_`.
+/// A process-wide counter keeps names unique when titles repeat.
+fn gitlab_section_name(title: &str) -> String {
+    let id = NEXT_SECTION.fetch_add(1, Ordering::Relaxed);
+    format!("{}_{id}", markers::gitlab_slug(title))
+}
+
+impl Provider {
+    /// The marker that opens a group, and the name its end marker must repeat, if any.
+    ///
+    /// GitLab sections get a unique name and the current time; TeamCity blocks are named after
+    /// their title.
+    fn open_group(self, title: &str, expanded: bool, color: bool) -> (String, Option<String>) {
+        match self {
+            Provider::GitLab => {
+                let name = gitlab_section_name(title);
+                let marker = markers::gitlab_section_start(&name, unix_now(), title);
+                (marker, Some(name))
+            }
+            Provider::TeamCity => (markers::open_marker(self, title), Some(title.to_owned())),
+            _ => (
+                markers::open_marker_with(self, title, expanded, color),
+                None,
+            ),
+        }
+    }
+
+    /// The end marker for a group [`open_group`](Self::open_group) opened, empty if there is none.
+    fn close_group(
+        self,
+        title: &str,
+        section: Option<&str>,
+        expanded: bool,
+        color: bool,
+    ) -> String {
+        match (self, section) {
+            (Provider::GitLab, Some(name)) => markers::gitlab_section_end(name, unix_now()),
+            (Provider::TeamCity, Some(name)) => markers::close_marker(self, name),
+            _ => markers::close_marker_with(self, title, expanded, color),
+        }
+    }
+}
+
+/// Stream that markers are written to. See [`set_output_stream`] and [`GroupBuilder::stream`].
+#[derive(Clone, Default)]
+pub enum OutputStream {
+    #[default]
+    Stdout,
+    Stderr,
+    /// A writer shared with the rest of the program, locked for each write and flushed after it.
+    ///
+    /// Two `Custom` streams are equal if they share the same writer.
+    Custom(Arc<Mutex<dyn Write + Send>>),
+}
+
+impl std::fmt::Debug for OutputStream {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            OutputStream::Stdout => f.write_str("Stdout"),
+            OutputStream::Stderr => f.write_str("Stderr"),
+            OutputStream::Custom(_) => f.write_str("Custom(..)"),
+        }
+    }
+}
+
+impl PartialEq for OutputStream {
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (OutputStream::Custom(a), OutputStream::Custom(b)) => Arc::ptr_eq(a, b),
+            (OutputStream::Stdout, OutputStream::Stdout)
+            | (OutputStream::Stderr, OutputStream::Stderr) => true,
+            _ => false,
+        }
+    }
+}
+
+impl Eq for OutputStream {}
+
+impl OutputStream {
+    /// Writes `bytes` straight to the stream, bypassing [`FlushMode::Deferred`] and [`with_batch`].
+    fn write_now(&self, bytes: &[u8]) -> bool {
+        match self {
+            OutputStream::Stdout => write_and_flush(&mut std::io::stdout().lock(), bytes),
+            OutputStream::Stderr => write_and_flush(&mut std::io::stderr().lock(), bytes),
+            OutputStream::Custom(writer) => write_and_flush(
+                &mut *writer.lock().unwrap_or_else(|e| e.into_inner()),
+                bytes,
+            ),
+        }
+    }
+}
+
+static STREAM: Mutex<OutputStream> = Mutex::new(OutputStream::Stdout);
+
+/// Selects the stream for markers written by groups and annotations created after this call.
+///
+/// The default is stdout. CI runners merge stdout and stderr into one log and fold whatever lands
+/// between the markers, but the two streams are read independently, so their relative order is only
+/// as good as arrival time. Put markers on the stream your output uses: with `log`/`env_logger`,
+/// which write to stderr, that means:
+///
+/// ```rust
+/// ci_group::set_output_stream(ci_group::OutputStream::Stderr);
+/// let _g = ci_group::open("Build");
+/// eprintln!("INFO building"); // folded with the markers
+/// ```
+///
+/// One stream is enough for both: the runner folds stdout and stderr lines alike once they are
+/// merged into the log, within the ordering caveat above.
+pub fn set_output_stream(stream: OutputStream) {
+    *STREAM.lock().unwrap_or_else(|e| e.into_inner()) = stream;
+    AT_LINE_START.store(false, Ordering::Relaxed);
+}
+
+/// Returns the stream selected by [`set_output_stream`].
+pub fn output_stream() -> OutputStream {
+    STREAM.lock().unwrap_or_else(|e| e.into_inner()).clone()
+}
+
+/// When markers start with a line break. See [`set_newline_guard`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum NewlineGuard {
+    /// Every marker starts with `\n`, in case earlier output didn't end its line.
+    #[default]
+    Always,
+    /// Skip the `\n` when the last byte the crate wrote to the selected output was a newline.
+    WhenNeeded,
+}
+
+static GUARD_WHEN_NEEDED: AtomicBool = AtomicBool::new(false);
+/// The last byte written to the selected output through the crate was `\n`.
+static AT_LINE_START: AtomicBool = AtomicBool::new(false);
+
+/// Chooses whether markers always start with a line break.
+///
+/// A marker must start at the beginning of a line, so by default each one is preceded by `\n`,
+/// which leaves a blank line whenever the previous output already ended with one. With
+/// [`NewlineGuard::WhenNeeded`] the crate remembers whether its own last write to the
+/// [global writer](set_writer) or [selected stream](set_output_stream) ended a line, and only
+/// breaks the line when it didn't.
+///
+/// The crate only sees what it writes itself: markers, and anything written through [`output`].
+/// A `print!` that bypasses it goes unnoticed, so only use `WhenNeeded` when all log output goes
+/// through [`output`]. Writers passed to [`Group::with_writer`] always get the guard.
+pub fn set_newline_guard(guard: NewlineGuard) {
+    GUARD_WHEN_NEEDED.store(guard == NewlineGuard::WhenNeeded, Ordering::Relaxed);
+}
+
+/// Returns the behaviour selected by [`set_newline_guard`].
+pub fn newline_guard() -> NewlineGuard {
+    if GUARD_WHEN_NEEDED.load(Ordering::Relaxed) {
+        NewlineGuard::WhenNeeded
+    } else {
+        NewlineGuard::Always
+    }
+}
+
+/// How the crate ends the lines it writes. See [`set_line_ending`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum LineEnding {
+    /// `\n`, which every supported CI reads.
+    #[default]
+    Lf,
+    /// `\r\n`, for Windows agents and log tooling that expect it.
+    CrLf,
+}
+
+impl LineEnding {
+    fn as_str(self) -> &'static str {
+        match self {
+            LineEnding::Lf => "\n",
+            LineEnding::CrLf => "\r\n",
+        }
+    }
+}
+
+static CRLF: AtomicBool = AtomicBool::new(false);
+
+/// Chooses the line break in markers, annotations and [`log`] lines.
+///
+/// Markers are built with `\n`; providers that need more, like GitLab's `\r\x1b[0K` after each
+/// section marker, get it from [`markers`]. [`LineEnding::CrLf`] then writes every bare `\n` as
+/// `\r\n`, for Azure Pipelines on Windows agents whose log tooling is picky. What you write
+/// yourself, through [`output`] or otherwise, is left alone.
+pub fn set_line_ending(ending: LineEnding) {
+    CRLF.store(ending == LineEnding::CrLf, Ordering::Relaxed);
+}
+
+/// Returns the line ending selected by [`set_line_ending`].
+pub fn line_ending() -> LineEnding {
+    if CRLF.load(Ordering::Relaxed) {
+        LineEnding::CrLf
+    } else {
+        LineEnding::Lf
+    }
+}
+
+/// `text`, with each bare `\n` written as the [selected line ending](set_line_ending). One that
+/// already follows a `\r`, as in a custom provider's markers, is left alone.
+fn with_line_ending(text: String) -> String {
+    if line_ending() == LineEnding::Lf {
+        return text;
+    }
+    let mut converted = String::with_capacity(text.len() + text.len() / 8);
+    let mut after_cr = false;
+    for c in text.chars() {
+        if c == '\n' && !after_cr {
+            converted.push('\r');
+        }
+        converted.push(c);
+        after_cr = c == '\r';
+    }
+    converted
+}
+
+/// The title length [`set_max_title_len`] starts at, in bytes.
+pub const DEFAULT_MAX_TITLE_LEN: usize = 4096;
+
+static MAX_TITLE_LEN: AtomicUsize = AtomicUsize::new(DEFAULT_MAX_TITLE_LEN);
+
+/// Caps group titles at `len` bytes; longer ones are cut at a character boundary and end in `…`,
+/// which counts towards `len`.
+///
+/// Some CI UIs choke on multi-kilobyte titles, such as a whole command line passed by mistake.
+/// Applies to groups opened and titles [set](Group::set_title) afterwards.
+pub fn set_max_title_len(len: usize) {
+    MAX_TITLE_LEN.store(len, Ordering::Relaxed);
+}
+
+/// Returns the limit selected by [`set_max_title_len`].
+pub fn max_title_len() -> usize {
+    MAX_TITLE_LEN.load(Ordering::Relaxed)
+}
+
+/// [`MAX_DEPTH`] value for "use the provider's default"; other values are the limit plus one.
+const PROVIDER_MAX_DEPTH: usize = 0;
+
+static MAX_DEPTH: AtomicUsize = AtomicUsize::new(PROVIDER_MAX_DEPTH);
+
+/// Caps how many groups of one provider can write markers nested on a thread at once.
+///
+/// Groups opened deeper are [flattened](Group) into the innermost one that wrote markers: they
+/// emit nothing but still count towards [`current_depth`]. `None` restores the defaults, `1` on
+/// GitHub Actions, which can't nest, and unlimited elsewhere. `Some(0)` silences every group.
+///
+/// ```rust
+/// ci_group::set_max_depth(Some(2));
+/// assert_eq!(ci_group::max_depth(), Some(2));
+/// # ci_group::set_max_depth(None);
+/// ```
+pub fn set_max_depth(depth: Option<usize>) {
+    let stored = depth.map_or(PROVIDER_MAX_DEPTH, |depth| depth.saturating_add(1));
+    MAX_DEPTH.store(stored, Ordering::Relaxed);
+}
+
+/// Returns the limit selected by [`set_max_depth`], or `None` for the provider defaults.
+pub fn max_depth() -> Option<usize> {
+    match MAX_DEPTH.load(Ordering::Relaxed) {
+        PROVIDER_MAX_DEPTH => None,
+        stored => Some(stored - 1),
+    }
+}
+
+/// The nesting limit in effect for `provider`.
+fn max_depth_for(provider: Provider) -> usize {
+    max_depth().unwrap_or(if provider.supports_nesting() {
+        usize::MAX
+    } else {
+        1
+    })
+}
+
+static TITLE_PREFIX: Mutex<String> = Mutex::new(String::new());
+
+fn title_prefix_lock() -> MutexGuard<'static, String> {
+    TITLE_PREFIX.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+/// Prepends `prefix` to the titles of groups opened and titles [set](Group::set_title) afterwards.
+///
+/// Set it once per package in a monorepo build instead of repeating it in every [`open`]. The
+/// prefix is used as given, so include the separator. An empty prefix turns it off.
+///
+/// ```rust
+/// ci_group::set_title_prefix("[api] ");
+/// let g = ci_group::open("Build");
+/// assert_eq!(g.label(), "[api] Build");
+/// # ci_group::set_title_prefix("");
+/// ```
+pub fn set_title_prefix(prefix: impl AsRef<str>) {
+    *title_prefix_lock() = prefix.as_ref().to_owned();
+}
+
+/// Returns the prefix selected by [`set_title_prefix`], empty if none.
+pub fn title_prefix() -> String {
+    title_prefix_lock().clone()
+}
+
+/// Sets the [title prefix](set_title_prefix) until the returned guard drops, then restores the
+/// previous one.
+///
+/// Guards nest, but the prefix is process-wide: groups opened on other threads meanwhile get it
+/// too.
+///
+/// ```rust
+/// for package in ["api", "web"] {
+///     let _prefix = ci_group::with_title_prefix(format!("[{package}] "));
+///     let _g = ci_group::open("Build"); // "[api] Build", then "[web] Build"
+/// }
+/// assert_eq!(ci_group::title_prefix(), "");
+/// ```
+pub fn with_title_prefix(prefix: impl AsRef<str>) -> TitlePrefixGuard {
+    let previous = std::mem::replace(&mut *title_prefix_lock(), prefix.as_ref().to_owned());
+    TitlePrefixGuard { previous }
+}
+
+/// Restores the previous title prefix when dropped. Returned by [`with_title_prefix`].
+#[derive(Debug)]
+#[must_use = "the prefix is removed when dropped. Bind it: let _p = with_title_prefix(...)"]
+pub struct TitlePrefixGuard {
+    previous: String,
+}
+
+impl Drop for TitlePrefixGuard {
+    fn drop(&mut self) {
+        *title_prefix_lock() = std::mem::take(&mut self.previous);
+    }
+}
+
+/// A normalized group title: on one line, and no longer than [`max_title_len`] bytes when built.
+///
+/// Groups build one from every title they're given, so this is where those rules live. Line breaks
+/// become spaces; everything else, ANSI colour codes included, is kept. Escaping for each
+/// provider's command syntax, such as defusing GitLab's `\x1b[0K` section markers, happens later,
+/// when the marker is written.
+///
+/// Pass a `Title` wherever a title is expected to build it once and reuse it; normalizing again
+/// doesn't change it.
+///
+/// ```rust
+/// use ci_group::Title;
+///
+/// let title = Title::from("Build\r\nall");
+/// assert_eq!(title.as_str(), "Build all");
+/// let _g = ci_group::open(&title);
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Title(String);
+
+impl Title {
+    /// The normalized title.
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+
+    /// Returns the normalized title as a `String`.
+    pub fn into_string(self) -> String {
+        self.0
+    }
+}
+
+impl From<&str> for Title {
+    fn from(title: &str) -> Self {
+        if is_clean_title(title) {
+            return Title(title.to_owned());
+        }
+        Title(markers::truncate_title(
+            &sanitize_title(title),
+            max_title_len(),
+        ))
+    }
+}
+
+impl From<String> for Title {
+    fn from(title: String) -> Self {
+        Title::from(title.as_str())
+    }
+}
+
+impl From<&String> for Title {
+    fn from(title: &String) -> Self {
+        Title::from(title.as_str())
+    }
+}
+
+impl AsRef<str> for Title {
+    fn as_ref(&self) -> &str {
+        &self.0
+    }
+}
+
+impl std::fmt::Display for Title {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+/// Returns true if [`Title`] would leave `title` as it is.
+fn is_clean_title(title: &str) -> bool {
+    title.len() <= max_title_len() && !title.contains(['\n', '\r'])
+}
+
+/// A title as groups store it: [prefixed](set_title_prefix) and normalized as a [`Title`].
+fn clean_title(title: &str) -> String {
+    let prefix = title_prefix_lock();
+    if prefix.is_empty() {
+        return Title::from(title).into_string();
+    }
+    Title::from(format!("{prefix}{title}")).into_string()
+}
+
+/// [`clean_title`], borrowing `title` instead of copying it when there's nothing to change.
+fn clean_static_title(title: &'static str) -> Cow<'static, str> {
+    if is_clean_title(title) && title_prefix_lock().is_empty() {
+        Cow::Borrowed(title)
+    } else {
+        Cow::Owned(clean_title(title))
+    }
+}
+
+/// A writer for the destination markers go to: the [global writer](set_writer) if installed,
+/// otherwise the [selected stream](set_output_stream).
+///
+/// Writes are serialized with markers and tracked for [`NewlineGuard::WhenNeeded`].
+///
+/// ```rust
+/// use std::io::Write;
+///
+/// let _g = ci_group::open("Build");
+/// writeln!(ci_group::output(), "Building...").unwrap();
+/// ```
+pub fn output() -> Output {
+    Output(())
+}
+
+/// Handle returned by [`output`].
+#[derive(Debug)]
+pub struct Output(());
+
+impl Write for Output {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        Sink::selected().write_serialized(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+static INDENT_LOGS: AtomicBool = AtomicBool::new(false);
+
+/// Indents lines written through [`log`] and [`glog!`](crate::glog) by two spaces per banner group open on the
+/// thread.
+///
+/// Providers without folding only get header and footer banners, so nested output otherwise runs
+/// together with its surroundings. Native groups (GitHub, Azure, GitLab, Buildkite, TeamCity) are
+/// never indented. Output that doesn't go through [`log`], `println!` included, isn't either.
+pub fn set_indent_logs(indent: bool) {
+    INDENT_LOGS.store(indent, Ordering::Relaxed);
+}
+
+/// Returns the setting selected by [`set_indent_logs`].
+pub fn indent_logs() -> bool {
+    INDENT_LOGS.load(Ordering::Relaxed)
+}
+
+/// Number of groups open on this thread that printed a banner.
+fn banner_depth() -> usize {
+    let thread = thread::current().id();
+    active_groups()
+        .iter()
+        .filter(|group| {
+            group.thread == thread && group.emitted && markers::uses_banners(group.provider)
+        })
+        .count()
+}
+
+/// Writes one line through the same locked, flushed writer as the markers. See [`glog!`](crate::glog).
+///
+/// The line is written in one piece after any marker already written, so it always lands inside
+/// the groups open at that point. Outside CI it's printed to the same stream all the same. See
+/// [`set_indent_logs`] for indenting it.
+pub fn log(args: std::fmt::Arguments) {
+    let text = args.to_string();
+    let depth = if indent_logs() { banner_depth() } else { 0 };
+    let indent = "  ".repeat(depth);
+    let mut line = String::with_capacity(text.len() + indent.len() + 1);
+    // A trailing line break ends the last line rather than starting an empty one.
+    let text = text.strip_suffix('\n').unwrap_or(&text);
+    for part in text.split('\n') {
+        line.push_str(&indent);
+        line.push_str(part);
+        line.push_str(line_ending().as_str());
+    }
+    Sink::selected().write_serialized(line.as_bytes());
+}
+
+static WRITER: Mutex<Option<Box<dyn Write + Send>>> = Mutex::new(None);
+
+fn global_writer() -> MutexGuard<'static, Option<Box<dyn Write + Send>>> {
+    WRITER.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+/// Redirects markers from groups and annotations created after this call to `writer`.
+///
+/// Takes precedence over [`set_output_stream`]. Useful for libraries that route their own output,
+/// or to capture markers in tests. Writes are serialized, so groups may be opened from any thread.
+///
+/// ```rust
+/// ci_group::set_writer(std::io::sink());
+/// let _g = ci_group::open("Quiet");
+/// ```
+pub fn set_writer<W: Write + Send + 'static>(writer: W) {
+    *global_writer() = Some(Box::new(writer));
+    AT_LINE_START.store(false, Ordering::Relaxed);
+}
+
+/// Removes the writer installed by [`set_writer`], returning to the selected standard stream.
+pub fn take_writer() -> Option<Box<dyn Write + Send>> {
+    AT_LINE_START.store(false, Ordering::Relaxed);
+    global_writer().take()
+}
+
+/// When the crate flushes what it writes. See [`set_flush_mode`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum FlushMode {
+    /// Flush after every marker.
+    #[default]
+    Immediate,
+    /// Buffer output per thread and write it out when the thread's last open group closes, or on
+    /// [`flush`].
+    Deferred,
+}
+
+static DEFER_FLUSH: AtomicBool = AtomicBool::new(false);
+
+/// Chooses whether markers are written and flushed one by one.
+///
+/// By default every marker is written and flushed as soon as it's made, so nothing is lost if the
+/// process dies. Programs that open many small groups can trade that for fewer syscalls with
+/// [`FlushMode::Deferred`]: while a thread has a group open, what the crate writes to stdout,
+/// stderr or the [global writer](set_writer) on that thread is buffered, then written out in one
+/// piece and flushed when the thread's last open group closes, when [`flush`] is called, or when
+/// the thread exits. A [`Group::with_writer`] writer gets a `BufWriter`, flushed when its group
+/// closes. Groups open on other threads don't hold this thread's output back.
+///
+/// Only what goes through the crate is buffered: markers, annotations, [`log`], [`output`] and
+/// writes to a [`Group`]. Output printed directly, with `println!` or `eprintln!`, isn't, so it
+/// lands before the markers meant to surround it; write it through [`glog!`](crate::glog) instead.
+///
+/// Output still buffered when the process exits or aborts is lost, so call [`flush`] before
+/// `std::process::exit`.
+pub fn set_flush_mode(mode: FlushMode) {
+    DEFER_FLUSH.store(mode == FlushMode::Deferred, Ordering::Relaxed);
+}
+
+/// Returns the mode selected by [`set_flush_mode`].
+pub fn flush_mode() -> FlushMode {
+    if DEFER_FLUSH.load(Ordering::Relaxed) {
+        FlushMode::Deferred
+    } else {
+        FlushMode::Immediate
+    }
+}
+
+/// Flushes the [global writer](set_writer), stdout and stderr.
+///
+/// Only needed with [`FlushMode::Deferred`], or inside [`with_batch`], whose held output is written
+/// out too. Errors are ignored.
+pub fn flush() {
+    let _serialized = emit_lock();
+    write_deferred();
+    let held = BATCH
+        .try_with(|batch| batch.borrow_mut().as_mut().map(std::mem::take))
+        .ok()
+        .flatten();
+    if let Some(bytes) = held.filter(|bytes| !bytes.is_empty()) {
+        let _ = std::io::stdout().lock().write_all(&bytes);
+    }
+    flush_writers();
+}
+
+/// Flushes the global writer, stdout and stderr. Callers hold [`emit_lock`].
+fn flush_writers() {
+    if let Some(writer) = global_writer().as_mut() {
+        let _ = writer.flush();
+    }
+    let _ = std::io::stdout().flush();
+    let _ = std::io::stderr().flush();
+}
+
+/// Where [`FlushMode::Deferred`] output goes once it's written out.
+enum Target {
+    Stdout,
+    Stderr,
+    Global,
+    Custom(Arc<Mutex<dyn Write + Send>>),
+}
+
+impl PartialEq for Target {
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (Target::Custom(a), Target::Custom(b)) => Arc::ptr_eq(a, b),
+            (Target::Stdout, Target::Stdout)
+            | (Target::Stderr, Target::Stderr)
+            | (Target::Global, Target::Global) => true,
+            _ => false,
+        }
+    }
+}
+
+/// A thread's [`FlushMode::Deferred`] output, in order, merged per run of the same target.
+///
+/// Written out when the thread exits, as a last resort.
+struct Deferred(Vec<(Target, Vec<u8>)>);
+
+impl Drop for Deferred {
+    fn drop(&mut self) {
+        if !self.0.is_empty() {
+            let _serialized = emit_lock();
+            write_chunks(std::mem::take(&mut self.0));
+            flush_writers();
+        }
+    }
+}
+
+thread_local! {
+    static DEFERRED: RefCell<Deferred> = const { RefCell::new(Deferred(Vec::new())) };
+}
+
+/// Appends `bytes` to this thread's deferred output, or returns false if they must be written now.
+///
+/// That's under [`FlushMode::Immediate`], and when no group is open on this thread, in which case
+/// what was deferred is written out first, so it stays in order.
+fn defer(target: Target, bytes: &[u8]) -> bool {
+    if flush_mode() == FlushMode::Immediate {
+        return false;
+    }
+    if current_depth() == 0 {
+        write_deferred();
+        return false;
+    }
+    DEFERRED
+        .try_with(|deferred| {
+            let chunks = &mut deferred.borrow_mut().0;
+            match chunks.last_mut() {
+                Some((last, held)) if *last == target => held.extend_from_slice(bytes),
+                _ => chunks.push((target, bytes.to_vec())),
+            }
+        })
+        .is_ok()
+}
+
+/// Writes out this thread's deferred output, unflushed. Callers hold [`emit_lock`].
+fn write_deferred() {
+    let chunks = DEFERRED
+        .try_with(|deferred| std::mem::take(&mut deferred.borrow_mut().0))
+        .unwrap_or_default();
+    write_chunks(chunks);
+}
+
+/// Writes each chunk in one `write_all`, leaving the flush to the caller. [`flush_writers`] doesn't
+/// know custom streams, so those are flushed here.
+fn write_chunks(chunks: Vec<(Target, Vec<u8>)>) {
+    if chunks.is_empty() {
+        return;
+    }
+    for (target, bytes) in &chunks {
+        let _ = match target {
+            Target::Stdout => std::io::stdout().lock().write_all(bytes),
+            Target::Stderr => std::io::stderr().lock().write_all(bytes),
+            Target::Global => match global_writer().as_mut() {
+                Some(writer) => writer.write_all(bytes),
+                // Removed since; the bytes go where the global sink would send them now.
+                None => {
+                    output_stream().write_now(bytes);
+                    Ok(())
+                }
+            },
+            Target::Custom(writer) => {
+                write_and_flush(
+                    &mut *writer.lock().unwrap_or_else(|e| e.into_inner()),
+                    bytes,
+                );
+                Ok(())
+            }
+        };
+    }
+}
+
+thread_local! {
+    /// What [`with_batch`] holds back from stdout on this thread.
+    static BATCH: RefCell<Option<Vec<u8>>> = const { RefCell::new(None) };
+}
+
+/// Runs `f` with everything this thread writes to stdout through the crate held in memory, then
+/// writes it out in one piece.
+///
+/// Per marker, stdout is otherwise locked and flushed again; tools emitting thousands of them in a
+/// tight loop save most of that. If `f` panics, what was batched is still written. A batch inside
+/// another one joins it.
+///
+/// Only what goes through the crate is held: markers, annotations, [`Batch::log`], [`glog!`](crate::glog) and
+/// [`output`]. `println!`, child processes and other threads are written as usual, and no lock
+/// stops them, so what they print during the batch lands *before* the markers meant to surround
+/// it. Inside the batch, write through [`Batch::log`] or [`glog!`](crate::glog) instead, or call [`flush`]
+/// first, as [`run_in_group`] does. The [global writer](set_writer) and stderr aren't batched.
+///
+/// ```rust
+/// ci_group::with_batch(|batch| {
+///     for crate_name in ["core", "api", "cli"] {
+///         let _g = batch.open(crate_name);
+///         batch.log(format_args!("checking {crate_name}"));
+///     }
+/// });
+/// ```
+pub fn with_batch<T, F: FnOnce(&Batch) -> T>(f: F) -> T {
+    /// Writes out the batch, on return or unwind.
+    struct End;
+
+    impl Drop for End {
+        fn drop(&mut self) {
+            flush();
+            BATCH.with(|batch| *batch.borrow_mut() = None);
+        }
+    }
+
+    let batch = Batch(PhantomData);
+    if BATCH.with(|batch| batch.borrow().is_some()) {
+        return f(&batch);
+    }
+    BATCH.with(|batch| *batch.borrow_mut() = Some(Vec::new()));
+    let _end = End;
+    f(&batch)
+}
+
+/// Handle passed to [`with_batch`]'s closure. Only valid on the thread running the batch.
+#[derive(Debug)]
+pub struct Batch(PhantomData<*const ()>);
+
+impl Batch {
+    /// Opens a group whose markers join the batch. Same as [`open`] on this thread.
+    pub fn open(&self, title: impl AsRef<str>) -> Group {
+        open(title)
+    }
+
+    /// Writes a line into the batch. Same as [`log`] on this thread.
+    pub fn log(&self, args: std::fmt::Arguments) {
+        log(args)
+    }
+}
+
+/// Appends `bytes` to this thread's [`with_batch`] buffer, or returns false outside a batch.
+fn hold_in_batch(bytes: &[u8]) -> bool {
+    BATCH
+        .try_with(|batch| match batch.borrow_mut().as_mut() {
+            Some(held) => {
+                held.extend_from_slice(bytes);
+                true
+            }
+            None => false,
+        })
+        .unwrap_or(false)
+}
+
+/// Where a group writes its markers.
+pub(crate) enum Sink {
+    Stdout,
+    Stderr,
+    /// The writer installed by [`set_writer`], falling back to the selected stream if removed.
+    Global,
+    Writer(Box<dyn Write + Send>),
+    /// An [`OutputStream::Custom`] writer.
+    Custom(Arc<Mutex<dyn Write + Send>>),
+    /// Held until the group closes, then written to the inner sink. See [`GroupBuilder::buffered`].
+    Buffered(Vec<u8>, Box<Sink>),
+}
+
+impl Sink {
+    /// The [`set_writer`] writer if installed, otherwise the stream selected by [`set_output_stream`].
+    pub(crate) fn selected() -> Self {
+        if global_writer().is_some() {
+            Sink::Global
+        } else {
+            Self::stream()
+        }
+    }
+
+    fn stream() -> Self {
+        Self::for_stream(output_stream())
+    }
+
+    fn for_stream(stream: OutputStream) -> Self {
+        match stream {
+            OutputStream::Stdout => Sink::Stdout,
+            OutputStream::Stderr => Sink::Stderr,
+            OutputStream::Custom(writer) => Sink::Custom(writer),
+        }
+    }
+
+    /// Writes a marker and flushes. Errors are ignored: a broken log stream must never fail the build.
+    ///
+    /// The marker is formatted up front and written with a single `write_all` while holding a
+    /// crate-wide lock, so markers from different threads never interleave mid-line, even through a
+    /// writer that accepts partial writes.
+    ///
+    /// Under [`NewlineGuard::WhenNeeded`] a leading `\n` is dropped when the shared output is
+    /// already at the start of a line.
+    pub(crate) fn write_marker(&mut self, marker: std::fmt::Arguments) {
+        let marker = with_line_ending(marker.to_string());
+        #[cfg(any(test, feature = "test-util"))]
+        remember_marker(&marker);
+        self.write_guarded(marker.as_bytes());
+    }
+
+    /// [`write_marker`](Self::write_marker) for bytes that may not be UTF-8.
+    fn write_guarded(&mut self, mut bytes: &[u8]) {
+        let _serialized = emit_lock();
+        if self.is_shared()
+            && newline_guard() == NewlineGuard::WhenNeeded
+            && AT_LINE_START.load(Ordering::Relaxed)
+        {
+            let line_break = line_ending().as_str().as_bytes();
+            bytes = bytes.strip_prefix(line_break).unwrap_or(bytes);
+        }
+        self.write_tracked(bytes);
+    }
+
+    /// Writes `bytes` as one unit under the crate-wide lock. See [`write_marker`](Self::write_marker).
+    fn write_serialized(&mut self, bytes: &[u8]) {
+        let _serialized = emit_lock();
+        self.write_tracked(bytes);
+    }
+
+    /// Whether banners should be colored: never with `NO_COLOR`, always with `FORCE_COLOR` or
+    /// `CLICOLOR_FORCE`, otherwise when writing to a terminal and `CLICOLOR` isn't `0`.
+    fn wants_color(&self) -> bool {
+        if env_is_non_empty("NO_COLOR") {
+            return false;
+        }
+        if env_is_truthy("FORCE_COLOR") || env_is_truthy("CLICOLOR_FORCE") {
+            return true;
+        }
+        if std::env::var_os("CLICOLOR").is_some_and(|v| v == "0") {
+            return false;
+        }
+        match self {
+            Sink::Stdout => std::io::stdout().is_terminal(),
+            Sink::Stderr => std::io::stderr().is_terminal(),
+            Sink::Global if global_writer().is_none() => Self::stream().wants_color(),
+            Sink::Buffered(_, inner) => inner.wants_color(),
+            Sink::Global | Sink::Writer(_) | Sink::Custom(_) => false,
+        }
+    }
+
+    /// The same destination, for the watchdog thread to write to, or `None` for a group's own
+    /// writer.
+    fn share(&self) -> Option<Sink> {
+        match self {
+            Sink::Stdout => Some(Sink::Stdout),
+            Sink::Stderr => Some(Sink::Stderr),
+            Sink::Global => Some(Sink::Global),
+            Sink::Custom(writer) => Some(Sink::Custom(Arc::clone(writer))),
+            // What it holds only comes out when the group closes, too late to point at a hang.
+            Sink::Buffered(_, inner) => inner.share(),
+            Sink::Writer(_) => None,
+        }
+    }
+
+    /// The global writer or a standard stream, which other groups and [`output`] also write to.
+    fn is_shared(&self) -> bool {
+        !matches!(self, Sink::Writer(_) | Sink::Buffered(..))
+    }
+
+    /// Switches a [`Sink::Buffered`] to its inner sink, writing out what it held if `keep`.
+    fn release(&mut self, keep: bool) {
+        if let Sink::Buffered(buffer, inner) = self {
+            let buffer = std::mem::take(buffer);
+            let mut inner = std::mem::replace(&mut **inner, Sink::Stdout);
+            if keep && !buffer.is_empty() {
+                inner.write_guarded(&buffer);
+            }
+            *self = inner;
+        }
+    }
+
+    fn write_tracked(&mut self, bytes: &[u8]) {
+        let written = self.write_all(bytes);
+        if let (true, Some(&last)) = (self.is_shared(), bytes.last()) {
+            // After a failed write the line may have been cut anywhere, so don't assume it ended.
+            AT_LINE_START.store(written && last == b'\n', Ordering::Relaxed);
+        }
+    }
+
+    /// Returns false if any destination failed to take all of `bytes`.
+    fn write_all(&mut self, bytes: &[u8]) -> bool {
+        match self {
+            Sink::Stdout => {
+                hold_in_batch(bytes)
+                    || defer(Target::Stdout, bytes)
+                    || write_and_flush(&mut std::io::stdout().lock(), bytes)
+            }
+            Sink::Stderr => {
+                defer(Target::Stderr, bytes)
+                    || write_and_flush(&mut std::io::stderr().lock(), bytes)
+            }
+            Sink::Global => {
+                // Checked before locking: writing out what was deferred locks the writer too.
+                let installed = global_writer().is_some();
+                if installed && defer(Target::Global, bytes) {
+                    return true;
+                }
+                let mut global = global_writer();
+                match global.as_mut() {
+                    Some(writer) => write_and_flush(writer, bytes),
+                    None => {
+                        drop(global);
+                        Self::stream().write_all(bytes)
+                    }
+                }
+            }
+            Sink::Writer(writer) => write_and_flush(writer, bytes),
+            Sink::Custom(writer) => {
+                defer(Target::Custom(writer.clone()), bytes)
+                    || write_and_flush(
+                        &mut *writer.lock().unwrap_or_else(|e| e.into_inner()),
+                        bytes,
+                    )
+            }
+            Sink::Buffered(buffer, _) => {
+                buffer.extend_from_slice(bytes);
+                true
+            }
+        }
+    }
+}
+
+#[cfg(any(test, feature = "test-util"))]
+thread_local! {
+    static LAST_MARKER: RefCell<Option<String>> = const { RefCell::new(None) };
+}
+
+#[cfg(any(test, feature = "test-util"))]
+fn remember_marker(marker: &str) {
+    if let Some(line) = marker.lines().rev().find(|line| !line.is_empty()) {
+        LAST_MARKER.with(|last| *last.borrow_mut() = Some(line.to_owned()));
+    }
+}
+
+/// Returns the last line of the most recent marker or command this thread wrote, or `None` if it
+/// hasn't written any.
+///
+/// For tests of tools built on this crate, without capturing their output. Groups that write
+/// nothing, outside CI for instance, leave it unchanged. Available with the `test-util` feature.
+///
+/// ```rust
+/// # #[cfg(feature = "test-util")] {
+/// let _g = ci_group::open_with("Build", ci_group::Mode::ForceGitHub);
+/// assert_eq!(ci_group::last_marker().as_deref(), Some("::group::Build"));
+/// # }
+/// ```
+#[cfg(any(test, feature = "test-util"))]
+pub fn last_marker() -> Option<String> {
+    LAST_MARKER.with(|last| last.borrow().clone())
+}
+
+/// Runs `f` as if on GitHub Actions and returns everything the crate wrote meanwhile, for snapshot
+/// tests. Same as [`capture_with`] with [`Provider::GitHub`].
+///
+/// ```rust
+/// # #[cfg(feature = "test-util")] {
+/// let out = ci_group::capture(|| {
+///     let _g = ci_group::open("Build");
+///     ci_group::glog!("compiling");
+/// });
+/// assert_eq!(out, "\n::group::Build\ncompiling\n\n::endgroup::\n");
+/// # }
+/// ```
+#[cfg(any(test, feature = "test-util"))]
+pub fn capture<F: FnOnce()>(f: F) -> String {
+    capture_with(Provider::GitHub, f)
+}
+
+/// Runs `f` with `provider` detected and a buffer as the [global writer](set_writer), and returns
+/// what was written to it.
+///
+/// Output is deterministic: timestamps and [durations](Group::timed) read as zero, and GitLab
+/// section names are numbered from zero. Afterwards the previous writer and detection are back,
+/// even if `f` panics. Output that bypasses the global writer, `println!` or a group with its own
+/// [writer](Group::with_writer) or [stream](GroupBuilder::stream), isn't captured. The state is
+/// process-wide, so don't capture on several threads at once. Available with the `test-util`
+/// feature.
+#[cfg(any(test, feature = "test-util"))]
+pub fn capture_with<F: FnOnce()>(provider: Provider, f: F) -> String {
+    #[derive(Clone, Default)]
+    struct Buffer(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for Buffer {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0
+                .lock()
+                .unwrap_or_else(|e| e.into_inner())
+                .extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    /// Puts back what [`capture_with`] replaced, on return or unwind.
+    struct Restore {
+        writer: Option<Box<dyn Write + Send>>,
+        provider: Option<Provider>,
+        sections: usize,
+    }
+
+    impl Drop for Restore {
+        fn drop(&mut self) {
+            FROZEN_CLOCK.store(false, Ordering::Relaxed);
+            *DETECTED.lock().unwrap_or_else(|e| e.into_inner()) = self.provider;
+            // Keep numbering past the sections opened before and during the capture.
+            NEXT_SECTION.fetch_add(self.sections, Ordering::Relaxed);
+            *global_writer() = self.writer.take();
+            AT_LINE_START.store(false, Ordering::Relaxed);
+        }
+    }
+
+    let buffer = Buffer::default();
+    let restore = Restore {
+        writer: global_writer().replace(Box::new(buffer.clone())),
+        provider: DETECTED
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .replace(provider),
+        sections: NEXT_SECTION.swap(0, Ordering::Relaxed),
+    };
+    AT_LINE_START.store(false, Ordering::Relaxed);
+    FROZEN_CLOCK.store(true, Ordering::Relaxed);
+    f();
+    drop(restore);
+    let out = buffer.0.lock().unwrap_or_else(|e| e.into_inner());
+    String::from_utf8_lossy(&out).into_owned()
+}
+
+/// Writes `bytes`, then flushes unless flushing is [deferred](FlushMode::Deferred). Every byte the
+/// crate logs goes through here.
+///
+/// Errors are swallowed, never returned or panicked on: a broken log stream must not fail the
+/// build. The flush is attempted even after a failed write, so whatever part did get through
+/// reaches the log. Returns whether the write itself succeeded; a failed flush only delays output.
+///
+/// Each marker, banners included, arrives here whole and goes out in one `write_all`, which
+/// resumes after short and [interrupted](std::io::ErrorKind::Interrupted) writes; so does the
+/// flush. Callers hold [`emit_lock`], so nothing the crate writes can land in between.
+fn write_and_flush(writer: &mut dyn Write, bytes: &[u8]) -> bool {
+    let written = writer.write_all(bytes).is_ok();
+    if flush_mode() == FlushMode::Immediate {
+        while let Err(e) = writer.flush() {
+            if e.kind() != std::io::ErrorKind::Interrupted {
+                break;
+            }
+        }
+    }
+    written
+}
+
+/// Serializes every write the crate makes, so markers from different threads never interleave.
+fn emit_lock() -> MutexGuard<'static, ()> {
+    static EMIT: Mutex<()> = Mutex::new(());
+    EMIT.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+/// A group that hasn't been dropped yet.
+struct ActiveGroup {
+    id: usize,
+    /// The thread the group was opened on; depth is counted per opening thread.
+    thread: ThreadId,
+    title: Cow<'static, str>,
+    provider: Provider,
+    /// Wrote open markers, so it counts towards [`max_depth`] for later groups on its thread.
+    emitted: bool,
+}
+
+/// Every open group in the process, in opening order.
+///
+/// Shared rather than thread-local so a group moved to another thread still leaves its opening
+/// thread's stack when it drops.
+static ACTIVE: Mutex<Vec<ActiveGroup>> = Mutex::new(Vec::new());
+static NEXT_GROUP_ID: AtomicUsize = AtomicUsize::new(0);
+
+fn active_groups() -> MutexGuard<'static, Vec<ActiveGroup>> {
+    ACTIVE.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+/// How many groups [`recent_groups`] remembers.
+pub const GROUP_HISTORY_LEN: usize = 64;
+
+/// A group as remembered by [`recent_groups`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GroupRecord {
+    pub title: String,
+    /// Groups open on the opening thread, this one included, as in [`current_depth`].
+    pub depth: usize,
+    pub thread: ThreadId,
+    pub opened: SystemTime,
+    /// `None` while the group is still open.
+    pub closed: Option<SystemTime>,
+}
+
+/// A [`GroupRecord`] as the history keeps it: a static title is shared rather than copied until the
+/// record is read. `record.title` stays empty.
+struct Remembered {
+    title: Cow<'static, str>,
+    record: GroupRecord,
+}
+
+/// The last [`GROUP_HISTORY_LEN`] groups opened, oldest first, keyed by group id.
+static HISTORY: Mutex<VecDeque<(usize, Remembered)>> = Mutex::new(VecDeque::new());
+
+fn history() -> MutexGuard<'static, VecDeque<(usize, Remembered)>> {
+    HISTORY.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+/// Returns the last `n` groups opened in the process, oldest first, including ones still open.
+///
+/// For failure reports that say where a run was: the records still open on the failing thread,
+/// by depth, are the path to the failing step. Every group counts, flattened and disabled ones
+/// too. Only the last [`GROUP_HISTORY_LEN`] are kept, so `n` is capped at that.
+///
+/// ```rust
+/// let _build = ci_group::open("Build");
+/// let _link = ci_group::open("link");
+/// let path: Vec<String> = ci_group::recent_groups(2)
+///     .into_iter()
+///     .filter(|record| record.closed.is_none())
+///     .map(|record| record.title)
+///     .collect();
+/// assert_eq!(path.join(" > "), "Build > link");
+/// ```
+pub fn recent_groups(n: usize) -> Vec<GroupRecord> {
+    let history = history();
+    let skip = history.len().saturating_sub(n);
+    history
+        .iter()
+        .skip(skip)
+        .map(|(_, remembered)| GroupRecord {
+            title: remembered.title.clone().into_owned(),
+            ..remembered.record.clone()
+        })
+        .collect()
+}
+
+fn remember_group(id: usize, remembered: Remembered) {
+    let mut history = history();
+    if history.len() == GROUP_HISTORY_LEN {
+        history.pop_front();
+    }
+    history.push_back((id, remembered));
+}
+
+/// Applies `update` to the remembered record of group `id`, if it's still in the history.
+fn update_group_record(id: usize, update: impl FnOnce(&mut Remembered)) {
+    if let Some((_, record)) = history().iter_mut().rfind(|(group, _)| *group == id) {
+        update(record);
+    }
+}
+
+/// Title of the innermost GitHub group this thread has open, the one later GitHub groups are
+/// flattened into.
+pub(crate) fn open_github_group() -> Option<String> {
+    let thread = thread::current().id();
+    active_groups()
+        .iter()
+        .rfind(|group| {
+            group.thread == thread && group.provider == Provider::GitHub && group.emitted
+        })
+        .map(|group| group.title.to_string())
+}
+
+/// Number of groups currently open on this thread, including flattened and disabled ones.
+///
+/// ```rust
+/// assert_eq!(ci_group::current_depth(), 0);
+/// let _outer = ci_group::open("Outer");
+/// let _inner = ci_group::open("Inner");
+/// assert_eq!(ci_group::current_depth(), 2);
+/// ```
+pub fn current_depth() -> usize {
+    let thread = thread::current().id();
+    active_groups()
+        .iter()
+        .filter(|group| group.thread == thread)
+        .count()
+}
+
+/// Titles of the groups currently open on this thread, outermost first.
+pub fn active_titles() -> Vec<String> {
+    let thread = thread::current().id();
+    active_groups()
+        .iter()
+        .filter(|group| group.thread == thread)
+        .map(|group| group.title.to_string())
+        .collect()
+}
+
+/// A collapsible log group. Closes automatically when dropped.
+///
+/// On GitHub Actions, a group opened while another is already open on the same thread is flattened:
+/// it writes no markers and its output lands in the outer group. [`set_max_depth`] moves that
+/// limit, on any provider. See the crate docs on nesting.
+///
+/// Write errors are ignored, so dropping a group never panics, even when stdout is a closed pipe.
+///
+/// # Threads
+///
+/// A group is `Send`, so it can be handed to another thread and closed there. It still counts
+/// towards the depth of the thread that opened it until then. It isn't `Sync`: the writer and
+/// [`on_close`](Self::on_close) callback it may own only have to be `Send`.
+///
+/// ```rust
+/// let g = ci_group::open("Background");
+/// std::thread::spawn(move || {
+///     println!("working...");
+///     drop(g);
+/// })
+/// .join()
+/// .unwrap();
+/// ```
+///
+/// For the same reason, a callback that isn't `Send` is rejected:
+///
+/// ```compile_fail
+/// let shared = std::rc::Rc::new(());
+/// let _g = ci_group::open("Lint").on_close(move || drop(shared));
+/// ```
+#[must_use = "group closes immediately when dropped. Bind it: let _g = open(...)"]
+pub struct Group {
+    /// Key into [`ACTIVE`].
+    id: usize,
+    provider: Provider,
+    sink: Sink,
+    title: Cow<'static, str>,
+    /// GitLab section name or TeamCity block name as opened, which the end marker must repeat even
+    /// after [`set_title`](Self::set_title).
+    section: Option<String>,
+    started: Instant,
+    timed: bool,
+    /// Opened beyond [`max_depth`] and flattened into its parent; writes nothing.
+    suppressed: bool,
+    expanded: bool,
+    /// A GitHub group written as a banner: [expanded](GroupBuilder::expanded), or under act.
+    banner: bool,
+    /// [`Mode::LocalBanner`] outside CI.
+    local_banner: bool,
+    /// Depth on the opening thread, for [`Mode::JsonEvents`] only.
+    json_depth: Option<usize>,
+    /// The provider a [`Mode::DryRun`] group records; it writes nothing.
+    dry_run: Option<Provider>,
+    on_close: Option<Box<dyn FnOnce() + Send>>,
+    /// Closed by [`discard`](Self::discard); writes no close marker.
+    discarded: bool,
+    /// Has an entry in [`WATCHDOGS`], from [`GroupBuilder::warn_after`].
+    watched: bool,
+}
+
+impl Group {
+    /// Creates a new group with the given title, writing to the [global writer](set_writer) or the
+    /// [selected stream](set_output_stream).
+    ///
+    /// Line breaks in the title are replaced with spaces; a raw newline would end the marker early
+    /// and leak the rest of the title as a stray log line.
+    pub fn new(title: impl AsRef<str>) -> Self {
+        GroupBuilder::new().title(title).open()
+    }
+
+    /// Creates a group only if `enabled`; otherwise a group that writes nothing, as with
+    /// [`Mode::Disabled`].
+    ///
+    /// ```rust
+    /// let verbose = std::env::var_os("VERBOSE").is_some();
+    /// let _g = ci_group::Group::maybe("Details", verbose);
+    /// ```
+    pub fn maybe(title: impl AsRef<str>, enabled: bool) -> Self {
+        let mode = if enabled { Mode::Auto } else { Mode::Disabled };
+        GroupBuilder::new().title(title).mode(mode).open()
+    }
+
+    /// Creates a new group that writes its markers to `writer` instead of stdout or stderr.
+    ///
+    /// The opening marker is written immediately; the closing marker goes to the same writer on drop.
+    ///
+    /// ```rust
+    /// let _g = ci_group::Group::with_writer("Build", std::io::stderr());
+    /// eprintln!("Building...");
+    /// ```
+    pub fn with_writer<W: Write + Send + 'static>(title: impl AsRef<str>, writer: W) -> Self {
+        Self::start(
+            GroupBuilder::new().title(title),
+            Sink::Writer(Box::new(writer)),
+        )
+    }
+
+    /// Reports how long the group was open once it closes.
+    ///
+    /// The line is printed after the end marker so it stays visible when the group is collapsed:
+    ///
+    /// ```text
+    /// ::endgroup::
+    /// Build took 3.2s
+    /// ```
+    ///
+    /// Nothing is printed outside CI. The time is taken on drop, so a panic still reports the partial duration.
+    pub fn timed(mut self) -> Self {
+        self.timed = true;
+        self
+    }
+
+    /// Runs `f` just before the end marker, so its output lands inside the group.
+    ///
+    /// ```rust
+    /// let warnings = 3;
+    /// let _g = ci_group::open("Lint").on_close(move || println!("{warnings} warnings"));
+    /// ```
+    ///
+    /// The callback also runs while unwinding from a panic. It must not panic itself: a panic during
+    /// unwinding aborts the process.
+    pub fn on_close<F: FnOnce() + Send + 'static>(mut self, f: F) -> Self {
+        self.on_close = Some(Box::new(f));
+        self
+    }
+
+    /// Closes the group now instead of at the end of scope.
+    ///
+    /// ```rust
+    /// let g = ci_group::open("Step");
+    /// println!("working...");
+    /// g.close(); // end marker is written here, not again at end of scope
+    /// ```
+    pub fn close(self) {
+        drop(self);
+    }
+
+    /// Closes the group without showing it.
+    ///
+    /// A [buffered](GroupBuilder::buffered) group drops its open marker and everything written to it
+    /// since, so nothing at all reaches the log. Otherwise the open marker is already out and only
+    /// the close marker and [timing](Self::timed) line are skipped, which leaves the rest of the log
+    /// inside the group on providers that fold.
+    ///
+    /// ```rust
+    /// use std::io::Write;
+    ///
+    /// let mut g = ci_group::GroupBuilder::new().title("Retry").buffered(true).open();
+    /// writeln!(g, "attempt 2").unwrap();
+    /// g.discard(); // neither the markers nor "attempt 2" are written
+    /// ```
+    pub fn discard(mut self) {
+        self.discarded = true;
+        drop(self);
+    }
+
+    /// The group's title, after line breaks were replaced and an overlong one
+    /// [truncated](set_max_title_len).
+    ///
+    /// ```rust
+    /// let g = ci_group::open("Build\nall");
+    /// assert_eq!(g.label(), "Build all");
+    /// ```
+    pub fn label(&self) -> &str {
+        &self.title
+    }
+
+    /// Replaces the title, for groups opened before their outcome is known.
+    ///
+    /// The open marker is already written, so only what comes later changes: the footer banner on
+    /// providers that print one (Jenkins, Bitbucket, Drone and the like), the [`timed`](Self::timed)
+    /// line, [`label`](Self::label) and [`active_titles`]. GitHub, Azure, GitLab and TeamCity end
+    /// markers don't show a title, so nothing changes there.
+    ///
+    /// ```rust
+    /// let mut g = ci_group::open("Processing...");
+    /// g.set_title("Processed 42 files");
+    /// assert_eq!(g.label(), "Processed 42 files");
+    /// ```
+    pub fn set_title(&mut self, title: impl AsRef<str>) {
+        self.title = Cow::Owned(clean_title(title.as_ref()));
+        if let Some(group) = active_groups().iter_mut().find(|group| group.id == self.id) {
+            group.title = self.title.clone();
+        }
+        update_group_record(self.id, |remembered| remembered.title = self.title.clone());
+    }
+
+    /// Returns true if the group writes CI markers, false if it's a no-op: outside CI,
+    /// [disabled](Mode::Disabled), or [flattened](Group) into an outer group.
+    ///
+    /// Lets callers skip work that only pays off inside a fold. [`Mode::LocalBanner`] banners and
+    /// [`Mode::JsonEvents`] lines don't count as markers.
+    ///
+    /// ```rust
+    /// let g = ci_group::open_with("Build", ci_group::Mode::Disabled);
+    /// assert!(!g.is_real());
+    /// ```
+    pub fn is_real(&self) -> bool {
+        self.provider.is_active() && !self.suppressed
+    }
+
+    /// Returns true if the group was opened with [`GroupBuilder::expanded`], whether or not the
+    /// provider honours it.
+    pub fn is_expanded(&self) -> bool {
+        self.expanded
+    }
+
+    /// Returns the provider the group resolved to when it opened, for provider-specific output
+    /// inside it. [`Provider::None`] outside CI and for [disabled](Mode::Disabled) or
+    /// [dry-run](Mode::DryRun) groups. A [flattened](Group) group still reports its provider; see
+    /// [`is_real`](Self::is_real).
+    ///
+    /// ```rust
+    /// use ci_group::{Mode, Provider};
+    ///
+    /// let g = ci_group::open_with("Build", Mode::ForceAzure);
+    /// assert_eq!(g.provider(), Provider::Azure);
+    /// ```
+    pub fn provider(&self) -> Provider {
+        self.provider
+    }
+
+    fn start(mut options: GroupBuilder, sink: Sink) -> Self {
+        let mut sink = match sink {
+            Sink::Writer(writer) if flush_mode() == FlushMode::Deferred => {
+                Sink::Writer(Box::new(std::io::BufWriter::new(writer)))
+            }
+            sink => sink,
+        };
+        if disabled_by_env() {
+            options.mode = Mode::Disabled;
+            options.provider = None;
+        }
+        let title = &match &options.title {
+            Cow::Borrowed(title) => clean_static_title(title),
+            Cow::Owned(title) => Cow::Owned(clean_title(title)),
+        };
+        let mut provider = options.resolve_provider();
+        let dry_run = (options.mode == Mode::DryRun).then_some(provider);
+        if let Some(resolved) = dry_run {
+            record(GroupAction::Open, resolved, title);
+            provider = Provider::None;
+        }
+        let expanded = options.expanded;
+        let banner = provider == Provider::GitHub && (expanded || running_under_act());
+        let local_banner = options.mode == Mode::LocalBanner && provider == Provider::None;
+        let json_events = options.mode == Mode::JsonEvents && provider == Provider::None;
+        let mut section = None;
+
+        let id = NEXT_GROUP_ID.fetch_add(1, Ordering::Relaxed);
+        let (suppressed, depth) = {
+            let thread = thread::current().id();
+            let mut active = active_groups();
+            let open = active
+                .iter()
+                .filter(|group| {
+                    group.thread == thread && group.provider == provider && group.emitted
+                })
+                .count();
+            let suppressed = provider.is_active() && open >= max_depth_for(provider);
+            // GitHub banners and expanded Azure groups are a heading, which nothing folds into.
+            let folds = !(banner || (expanded && provider == Provider::Azure));
+            active.push(ActiveGroup {
+                id,
+                thread,
+                title: title.clone(),
+                provider,
+                emitted: provider.is_active() && !suppressed && folds,
+            });
+            let depth = active.iter().filter(|group| group.thread == thread).count();
+            let record = GroupRecord {
+                title: String::new(),
+                depth,
+                thread,
+                opened: SystemTime::now(),
+                closed: None,
+            };
+            let remembered = Remembered {
+                title: title.clone(),
+                record,
+            };
+            remember_group(id, remembered);
+            (suppressed, depth)
+        };
+
+        if provider.is_active() && !suppressed {
+            let (marker, name) = provider.open_group(title, expanded || banner, sink.wants_color());
+            section = name;
+            let marker = if options.no_leading_newline {
+                marker.strip_prefix('\n').unwrap_or(&marker)
+            } else {
+                &marker
+            };
+            sink.write_marker(format_args!("{marker}"));
+        }
+        if local_banner {
+            sink.write_marker(format_args!("▶ {title}\n"));
+        }
+        let json_depth = json_events.then_some(depth);
+        if let Some(depth) = json_depth {
+            let event = markers::json_event("open", title, unix_now_millis(), depth);
+            sink.write_marker(format_args!("{event}"));
+        }
+        mirror_to_log(format_args!(
+            "OPEN provider={} title={title:?}",
+            provider.name()
+        ));
+
+        Group {
+            id,
+            provider,
+            sink,
+            title: title.clone(),
+            section,
+            started: Instant::now(),
+            timed: false,
+            suppressed,
+            expanded,
+            banner,
+            local_banner,
+            json_depth,
+            dry_run,
+            on_close: None,
+            discarded: false,
+            watched: false,
+        }
+    }
+}
+
+/// A group waiting for its [`GroupBuilder::warn_after`] timeout.
+struct Watched {
+    id: usize,
+    deadline: Instant,
+    timeout: Duration,
+    title: String,
+    provider: Provider,
+    /// Where the group's markers go.
+    sink: Sink,
+}
+
+/// The groups one background thread watches, and whether it has been started.
+static WATCHDOGS: Mutex<(Vec<Watched>, bool)> = Mutex::new((Vec::new(), false));
+
+/// Wakes the watchdog thread when a group is added.
+static WATCHDOG_WAKE: Condvar = Condvar::new();
+
+fn watchdogs() -> MutexGuard<'static, (Vec<Watched>, bool)> {
+    WATCHDOGS.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+/// Adds a group to the watchdog, starting its thread on first use.
+///
+/// Returns false if the thread couldn't be spawned; the group then just goes without a warning.
+fn watch(watched: Watched) -> bool {
+    let mut watchdogs = watchdogs();
+    if !watchdogs.1 {
+        let spawned = thread::Builder::new()
+            .name(String::from("ci_group-watchdog"))
+            .spawn(run_watchdog);
+        if spawned.is_err() {
+            return false;
+        }
+        watchdogs.1 = true;
+    }
+    watchdogs.0.push(watched);
+    WATCHDOG_WAKE.notify_one();
+    true
+}
+
+/// Removes a closing group. Waits for a warning being written, so it never comes after the close
+/// marker.
+fn unwatch(id: usize) {
+    watchdogs().0.retain(|watched| watched.id != id);
+}
+
+/// Sleeps until the next deadline, warns about the groups past theirs, and repeats.
+fn run_watchdog() {
+    let mut watchdogs = watchdogs();
+    loop {
+        warn_overdue(&mut watchdogs.0, Instant::now());
+        let next = watchdogs.0.iter().map(|watched| watched.deadline).min();
+        watchdogs = match next {
+            Some(deadline) => {
+                let timeout = deadline.saturating_duration_since(Instant::now());
+                let waited = WATCHDOG_WAKE.wait_timeout(watchdogs, timeout);
+                waited.unwrap_or_else(|e| e.into_inner()).0
+            }
+            None => WATCHDOG_WAKE
+                .wait(watchdogs)
+                .unwrap_or_else(|e| e.into_inner()),
+        };
+    }
+}
+
+/// Writes a warning for each group whose deadline is before `now`, and stops watching it.
+fn warn_overdue(watched: &mut Vec<Watched>, now: Instant) {
+    let (overdue, waiting) = std::mem::take(watched)
+        .into_iter()
+        .partition(|watched| watched.deadline <= now);
+    *watched = waiting;
+    for mut group in overdue {
+        let secs = group.timeout.as_secs_f64();
+        let message = format!("Group '{}' has been open for {secs:.1}s", group.title);
+        let line = Annotation::warning(&message).render(group.provider);
+        group.sink.write_marker(format_args!("{line}\n"));
+    }
+}
+
+impl Drop for Group {
+    fn drop(&mut self) {
+        if self.watched {
+            unwatch(self.id);
+        }
+        if let Some(on_close) = self.on_close.take() {
+            on_close();
+        }
+        let elapsed = if clock_frozen() {
+            Duration::ZERO
+        } else {
+            self.started.elapsed()
+        };
+
+        // Per thread, like the deferred output it writes out.
+        let outermost = {
+            let thread = thread::current().id();
+            let mut active = active_groups();
+            if let Some(index) = active.iter().rposition(|group| group.id == self.id) {
+                active.remove(index);
+            }
+            !active.iter().any(|group| group.thread == thread)
+        };
+        if !self.suppressed && !self.discarded {
+            self.write_close(elapsed);
+        }
+        self.sink.release(!self.discarded);
+        if let Some(provider) = self.dry_run {
+            record(GroupAction::Close, provider, &self.title);
+        }
+        mirror_to_log(format_args!("CLOSE title={:?}", self.title));
+        update_group_record(self.id, |remembered| {
+            remembered.record.closed = Some(SystemTime::now());
+        });
+
+        if flush_mode() == FlushMode::Deferred {
+            if let Sink::Writer(writer) = &mut self.sink {
+                let _ = writer.flush();
+            }
+            if outermost {
+                flush();
+            }
+        }
+    }
+}
+
+/// Writes to wherever the group's own markers go, serialized with them like [`output`].
+impl Write for Group {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.sink.write_serialized(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+/// A writer that wraps everything written through it in a group, for APIs that take a
+/// `&mut dyn Write`.
+///
+/// The group is only opened by the first non-empty write, with its markers going to `inner` like
+/// [`Group::with_writer`], so nothing written means no empty section in the log. It closes when
+/// the `GroupWriter` drops. As with [`Group`]'s own `Write`, errors from `inner` are swallowed.
+///
+/// ```rust
+/// use std::io::Write;
+///
+/// let mut out = ci_group::GroupWriter::new("Manifest", std::io::stdout());
+/// writeln!(out, "{{\"name\": \"ci_group\"}}").unwrap();
+/// // Dropping `out` closes the group.
+/// ```
+pub struct GroupWriter<W> {
+    title: String,
+    /// Until the first write, after which the group owns it.
+    inner: Option<W>,
+    group: Option<Group>,
+}
+
+impl<W: Write + Send + 'static> GroupWriter<W> {
+    /// Creates a writer that opens a group titled `title` on `inner` once something is written.
+    pub fn new(title: impl AsRef<str>, inner: W) -> Self {
+        GroupWriter {
+            title: title.as_ref().to_owned(),
+            inner: Some(inner),
+            group: None,
+        }
+    }
+
+    /// Returns true once something was written and the group opened.
+    pub fn is_open(&self) -> bool {
+        self.group.is_some()
+    }
+}
+
+impl<W: Write + Send + 'static> Write for GroupWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        let group = match &mut self.group {
+            Some(group) => group,
+            None => {
+                let inner = self
+                    .inner
+                    .take()
+                    .expect("inner writer is set until the group opens");
+                self.group.insert(Group::with_writer(&self.title, inner))
+            }
+        };
+        group.write(buf)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        match &mut self.group {
+            Some(group) => group.flush(),
+            None => Ok(()),
+        }
+    }
+}
+
+impl Group {
+    fn write_close(&mut self, elapsed: Duration) {
+        if self.provider.is_active() {
+            if self.provider.needs_explicit_close() {
+                self.write_close_marker();
+            }
+            if self.timed {
+                let line = markers::timing_line(self.provider, &self.title, elapsed);
+                self.sink.write_marker(format_args!("{line}"));
+            }
+        } else if self.local_banner {
+            self.sink.write_marker(format_args!("◀ (done)\n"));
+        } else if let Some(depth) = self.json_depth {
+            let event = markers::json_event("close", &self.title, unix_now_millis(), depth);
+            self.sink.write_marker(format_args!("{event}"));
+        }
+    }
+
+    fn write_close_marker(&mut self) {
+        let marker = self.provider.close_group(
+            &self.title,
+            self.section.as_deref(),
+            self.expanded || self.banner,
+            self.sink.wants_color(),
+        );
+        if !marker.is_empty() {
+            self.sink.write_marker(format_args!("{marker}"));
+        }
+    }
+}
+
+/// Configures a [`Group`] before opening it.
+///
+/// [`open`] and [`open_with`] are shorthands for the common cases; new options land here rather
+/// than as more free functions.
+///
+/// ```rust
+/// let _g = ci_group::GroupBuilder::new()
+///     .title("Test results")
+///     .expanded(true)
+///     .open();
+/// ```
+#[derive(Debug, Clone, Default)]
+#[must_use = "the group is only opened by .open()"]
+pub struct GroupBuilder {
+    title: Cow<'static, str>,
+    expanded: bool,
+    mode: Mode,
+    provider: Option<Provider>,
+    stream: Option<OutputStream>,
+    buffered: bool,
+    warn_after: Option<Duration>,
+    no_leading_newline: bool,
+}
+
+impl GroupBuilder {
+    /// Creates a builder for an untitled, collapsed group.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets the group title.
+    pub fn title(mut self, title: impl AsRef<str>) -> Self {
+        self.title = Cow::Owned(title.as_ref().to_owned());
+        self
+    }
+
+    /// Sets a title known at compile time, without copying it if it needs no normalizing.
+    ///
+    /// See [`open_static`].
+    pub fn static_title(mut self, title: &'static str) -> Self {
+        self.title = Cow::Borrowed(title);
+        self
+    }
+
+    /// Starts the group expanded instead of collapsed.
+    ///
+    /// - GitHub Actions can't open a group expanded, so the title is printed as a plain banner
+    ///   instead, keeping the output in view. Groups opened inside it fold as usual.
+    /// - Azure Pipelines prints a `##[section]` heading instead of a `##[group]`.
+    /// - Buildkite uses `+++` instead of `---`.
+    ///
+    /// Other providers ignore this, though the choice is still recorded on the group (see
+    /// [`Group::is_expanded`]).
+    pub fn expanded(mut self, expanded: bool) -> Self {
+        self.expanded = expanded;
+        self
+    }
+
+    /// Chooses whether markers follow detection, a forced provider, or are disabled.
+    pub fn mode(mut self, mode: Mode) -> Self {
+        self.mode = mode;
+        self
+    }
+
+    /// Uses `provider`'s markers instead of detecting one. Takes precedence over [`mode`](Self::mode).
+    pub fn provider_override(mut self, provider: Provider) -> Self {
+        self.provider = Some(provider);
+        self
+    }
+
+    /// Writes this group's markers to `stream`, whatever [`set_writer`] and [`set_output_stream`]
+    /// select.
+    ///
+    /// Both markers go to the same stream even if the global selection changes while the group is
+    /// open. [`OutputStream::Custom`] shares a writer between groups; for one only this group
+    /// uses, [`Group::with_writer`] is simpler.
+    ///
+    /// ```rust
+    /// use ci_group::{GroupBuilder, OutputStream};
+    ///
+    /// let _g = GroupBuilder::new()
+    ///     .title("Diagnostics")
+    ///     .stream(OutputStream::Stderr)
+    ///     .open();
+    /// eprintln!("checking...");
+    /// ```
+    pub fn stream(mut self, stream: OutputStream) -> Self {
+        self.stream = Some(stream);
+        self
+    }
+
+    /// Holds the group's markers, and what is written to the [`Group`] itself, until it closes.
+    ///
+    /// Everything then goes out in one piece, or not at all after [`Group::discard`]. Output
+    /// written any other way, `println!` and nested groups included, isn't held back and ends up
+    /// before the group in the log.
+    pub fn buffered(mut self, buffered: bool) -> Self {
+        self.buffered = buffered;
+        self
+    }
+
+    /// Emits a [warning](crate::warning) if the group is still open after `timeout`, to point at hung
+    /// steps.
+    ///
+    /// The warning goes where the group's markers do, written by one background thread that
+    /// watches every such group. Closing the group first cancels it, waiting for a warning being
+    /// written, so the warning never comes after the close marker. A group that writes no markers,
+    /// e.g. a [disabled](Mode::Disabled) or flattened one, is never warned about.
+    pub fn warn_after(mut self, timeout: Duration) -> Self {
+        self.warn_after = Some(timeout);
+        self
+    }
+
+    /// Whether the open marker starts with a line break, in case earlier output didn't end its
+    /// line. On by default.
+    ///
+    /// Turn it off when you know the output before the group always ends cleanly and the extra
+    /// blank line gets in the way, e.g. of a parser reading the log. Unlike
+    /// [`set_newline_guard`], nothing checks: a marker after an unfinished line isn't recognized.
+    pub fn leading_newline(mut self, leading_newline: bool) -> Self {
+        self.no_leading_newline = !leading_newline;
+        self
+    }
+
+    /// Opens the group.
+    pub fn open(mut self) -> Group {
+        let mut sink = match self.stream.take() {
+            Some(stream) => Sink::for_stream(stream),
+            None => Sink::selected(),
+        };
+        if self.buffered {
+            sink = Sink::Buffered(Vec::new(), Box::new(sink));
+        }
+        let warn_after = self.warn_after;
+        let mut group = Group::start(self, sink);
+        let warn_after = warn_after.filter(|_| group.is_real());
+        if let (Some(timeout), Some(sink)) = (warn_after, group.sink.share()) {
+            group.watched = watch(Watched {
+                id: group.id,
+                deadline: Instant::now() + timeout,
+                timeout,
+                title: group.title.to_string(),
+                provider: group.provider,
+                sink,
+            });
+        }
+        group
+    }
+
+    fn resolve_provider(&self) -> Provider {
+        self.provider.unwrap_or_else(|| self.mode.provider())
+    }
+}
+
+impl From<Mode> for GroupBuilder {
+    fn from(mode: Mode) -> Self {
+        GroupBuilder::new().mode(mode)
+    }
+}
+
+impl From<OutputStream> for GroupBuilder {
+    fn from(stream: OutputStream) -> Self {
+        GroupBuilder::new().stream(stream)
+    }
+}
+
+/// How a group decides which markers to write. See [`open_with`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Mode {
+    /// Detect the provider from the environment (what [`open`] does).
+    #[default]
+    Auto,
+    /// Write GitHub Actions markers even outside GitHub Actions.
+    ForceGitHub,
+    /// Write Azure Pipelines markers even outside Azure Pipelines.
+    ForceAzure,
+    /// Write nothing, even in CI.
+    Disabled,
+    /// Like `Auto`, but outside CI print readable `▶ title` / `◀ (done)` banners instead of nothing.
+    LocalBanner,
+    /// Write a JSON line per open and close instead of markers, for tooling. See
+    /// [`markers::json_event`].
+    JsonEvents,
+    /// Write nothing, but record each open and close with the provider `Auto` would pick. See
+    /// [`take_recorded_events`].
+    DryRun,
+}
+
+impl Mode {
+    fn provider(self) -> Provider {
+        match self {
+            Mode::Auto | Mode::LocalBanner | Mode::DryRun => Provider::current(),
+            Mode::ForceGitHub => Provider::GitHub,
+            Mode::ForceAzure => Provider::Azure,
+            Mode::Disabled | Mode::JsonEvents => Provider::None,
+        }
+    }
+}
+
+/// What a [`Mode::DryRun`] group did. See [`take_recorded_events`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GroupEvent {
+    pub action: GroupAction,
+    /// The provider whose markers the group would have written.
+    pub provider: Provider,
+    pub title: String,
+}
+
+/// Whether a [`GroupEvent`] opened or closed its group.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GroupAction {
+    Open,
+    Close,
+}
+
+thread_local! {
+    static RECORDED: RefCell<Vec<GroupEvent>> = const { RefCell::new(Vec::new()) };
+}
+
+fn record(action: GroupAction, provider: Provider, title: &str) {
+    let event = GroupEvent {
+        action,
+        provider,
+        title: title.to_owned(),
+    };
+    RECORDED.with(|recorded| recorded.borrow_mut().push(event));
+}
+
+/// Returns and clears the events [`Mode::DryRun`] groups recorded on this thread, oldest first.
+///
+/// Lets tests of CI tooling check which groups it opens without parsing its output. Every group
+/// is recorded, including ones a real run would [flatten](Group). A group closed on another
+/// thread records its close there.
+///
+/// ```rust
+/// use ci_group::{GroupAction, Mode};
+///
+/// drop(ci_group::open_with("Build", Mode::DryRun));
+/// let actions: Vec<GroupAction> = ci_group::take_recorded_events()
+///     .into_iter()
+///     .map(|event| event.action)
+///     .collect();
+/// assert_eq!(actions, [GroupAction::Open, GroupAction::Close]);
+/// ```
+pub fn take_recorded_events() -> Vec<GroupEvent> {
+    RECORDED.with(|recorded| std::mem::take(&mut *recorded.borrow_mut()))
+}
+
+/// Opens a log group with explicit control over the output format or the stream it goes to.
+///
+/// `options` is a [`Mode`], an [`OutputStream`], or a [`GroupBuilder`] for both; its title is
+/// replaced by `title`.
+///
+/// ```rust
+/// use ci_group::{Mode, OutputStream};
+///
+/// // Markers for later parsing, even when piping locally:
+/// let _g = ci_group::open_with("Build", Mode::ForceGitHub);
+/// let _diagnostics = ci_group::open_with("Diagnostics", OutputStream::Stderr);
+/// ```
+pub fn open_with(title: impl AsRef<str>, options: impl Into<GroupBuilder>) -> Group {
+    options.into().title(title).open()
+}
+
+/// Opens a log group that emits a warning if it's still open after `timeout`. See
+/// [`GroupBuilder::warn_after`].
+///
+/// ```rust
+/// use std::time::Duration;
+///
+/// let _g = ci_group::open_with_timeout("Integration tests", Duration::from_secs(600));
+/// ```
+pub fn open_with_timeout(title: impl AsRef<str>, timeout: Duration) -> Group {
+    GroupBuilder::new().title(title).warn_after(timeout).open()
+}
+
+/// Opens a new log group. Alias for [`Group::new`].
+pub fn open(title: impl AsRef<str>) -> Group {
+    Group::new(title)
+}
+
+/// [`open`] for a title known at compile time, which the group keeps without copying.
+///
+/// A title with nothing to normalize (no line breaks, not too long, and no
+/// [prefix](set_title_prefix) set) is used as is. That saves the 5 allocations
+/// spent copying and normalizing it, about 40% of what opening and closing a GitHub group costs;
+/// the rest goes into building and writing the markers. Other titles take the same path
+/// as with [`open`]. [`group!`](crate::group) uses this for string literals.
+///
+/// ```rust
+/// let _g = ci_group::open_static("Build");
+/// ```
+pub fn open_static(title: &'static str) -> Group {
+    GroupBuilder::new().static_title(title).open()
+}
+
+/// Runs `f` inside a log group and returns its value; the function form of [`group!`](crate::group).
+///
+/// The group closes when `f` returns. If `f` panics, the guard drops while the panic unwinds, so
+/// the group still closes, with the panic message inside it.
+///
+/// ```rust
+/// let total: u32 = ci_group::scope("Sum", || [1, 2, 3].iter().sum());
+/// assert_eq!(total, 6);
+/// ```
+pub fn scope<T, F: FnOnce() -> T>(title: impl AsRef<str>, f: F) -> T {
+    let _guard = open(title);
+    f()
+}
+
+/// Runs a fallible step inside a log group, reporting a failure as an [error](error) annotation.
+///
+/// On `Err`, the error's `Display` is emitted as an annotation inside the group, which then
+/// closes, and the error is returned as is. On `Ok` nothing else is written. The group closes on
+/// panic too, like with [`scope`].
+///
+/// ```rust
+/// let result: Result<(), String> = ci_group::step("Deploy", || Err("no credentials".into()));
+/// // Wrote `::error::no credentials` inside the "Deploy" group on GitHub Actions.
+/// assert!(result.is_err());
+/// ```
+pub fn step<T, E, F>(title: impl AsRef<str>, f: F) -> Result<T, E>
+where
+    E: std::fmt::Display,
+    F: FnOnce() -> Result<T, E>,
+{
+    let _guard = open(title);
+    let result = f();
+    if let Err(err) = &result {
+        error(&err.to_string());
+    }
+    result
+}
+
+/// Runs `cmd` inside a log group, waits for it, and returns its exit status.
+///
+/// The command line is echoed first, as `$ program args...`, and the markers are flushed before
+/// the child starts, so its output lands between them. The child writes straight to the inherited
+/// stdout and stderr, not to a [`set_writer`] writer. The group closes even if spawning or
+/// waiting fails; a non-zero exit is returned as the status, not as an error.
+///
+/// ```rust,no_run
+/// use std::process::Command;
+///
+/// let status = ci_group::run_in_group("Build", Command::new("cargo").args(["build", "--release"]))?;
+/// assert!(status.success());
+/// # Ok::<(), std::io::Error>(())
+/// ```
+pub fn run_in_group(title: impl AsRef<str>, cmd: &mut Command) -> std::io::Result<ExitStatus> {
+    let mut group = open(title);
+    let _ = writeln!(group, "$ {}", command_line(cmd));
+    flush();
+    let status = cmd.spawn().and_then(|mut child| child.wait());
+    // The child may have left a line unfinished.
+    AT_LINE_START.store(false, Ordering::Relaxed);
+    drop(group);
+    status
+}
+
+/// `cmd` as a shell would show it, quoting arguments that contain whitespace.
+fn command_line(cmd: &Command) -> String {
+    let mut line = cmd.get_program().to_string_lossy().into_owned();
+    for arg in cmd.get_args() {
+        let arg = arg.to_string_lossy();
+        if arg.is_empty() || arg.contains(char::is_whitespace) {
+            line.push_str(&format!(" '{arg}'"));
+        } else {
+            line.push(' ');
+            line.push_str(&arg);
+        }
+    }
+    line
+}
+
+/// Runs `body` on each item inside its own group, titled by `title_of`.
+///
+/// Each group closes before the next one opens, so items never nest. If `body` panics, the
+/// current item's group closes and the panic carries on.
+///
+/// ```rust
+/// let files = ["a.rs", "b.rs"];
+/// ci_group::group_each(files, |file| format!("Checking {file}"), |file| {
+///     println!("{file}: ok");
+/// });
+/// ```
+pub fn group_each<I, T, F, B>(items: I, mut title_of: F, mut body: B)
+where
+    I: IntoIterator,
+    T: AsRef<str>,
+    F: FnMut(&I::Item) -> T,
+    B: FnMut(I::Item),
+{
+    for item in items {
+        let _guard = open(title_of(&item));
+        body(item);
+    }
+}
+
+/// Groups whose lifetimes don't follow lexical scopes, closed together in a chosen order.
+///
+/// Dropping the set closes whatever is still open, newest first, as nested scopes would.
+///
+/// ```rust
+/// use ci_group::{CloseOrder, GroupSet};
+///
+/// let mut phases = GroupSet::new();
+/// phases.push("Fetch");
+/// phases.push("Build");
+/// assert_eq!(ci_group::current_depth(), 2);
+/// phases.close_all(CloseOrder::Fifo); // "Fetch" closes first
+/// assert_eq!(ci_group::current_depth(), 0);
+/// ```
+#[derive(Default)]
+pub struct GroupSet {
+    /// Oldest first.
+    groups: Vec<Group>,
+}
+
+/// The order [`GroupSet::close_all`] closes groups in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum CloseOrder {
+    /// Newest first, like nested scopes.
+    #[default]
+    Lifo,
+    /// Oldest first.
+    Fifo,
+}
+
+impl GroupSet {
+    /// Creates an empty set.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Opens a group titled `title` and keeps it open until the set closes it.
+    pub fn push(&mut self, title: impl AsRef<str>) -> &mut Group {
+        self.push_group(open(title))
+    }
+
+    /// Adds an already open group, for ones configured through [`GroupBuilder`].
+    pub fn push_group(&mut self, group: Group) -> &mut Group {
+        self.groups.push(group);
+        self.groups.last_mut().unwrap()
+    }
+
+    /// Number of groups the set holds open.
+    pub fn len(&self) -> usize {
+        self.groups.len()
+    }
+
+    /// Returns true if the set holds no open groups.
+    pub fn is_empty(&self) -> bool {
+        self.groups.is_empty()
+    }
+
+    /// Closes every group, in `order`.
+    pub fn close_all(mut self, order: CloseOrder) {
+        // Drop takes care of the rest, newest first.
+        if order == CloseOrder::Fifo {
+            for group in self.groups.drain(..) {
+                drop(group);
+            }
+        }
+    }
+}
+
+impl Drop for GroupSet {
+    fn drop(&mut self) {
+        while let Some(group) = self.groups.pop() {
+            drop(group);
+        }
+    }
+}
+
+/// What [`group_on_error_with`] does with the captured output when the closure succeeds.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum OnSuccess {
+    /// Print the output without a group.
+    #[default]
+    Print,
+    /// Drop the output.
+    Discard,
+}
+
+/// Runs `f` with an output buffer and only puts that output in a group if `f` returns `Err`.
+///
+/// Keeps noisy steps out of the way when they pass: on `Ok` the buffer is printed plainly, on
+/// `Err` it's replayed between group markers titled `title`. Either way it goes to the
+/// [global writer](set_writer) or [selected stream](set_output_stream), like markers do.
+///
+/// Only what `f` writes to the buffer is captured. `println!` and other direct writes to stdout
+/// appear immediately and ungrouped. If `f` panics, the buffered output is lost.
+///
+/// ```rust
+/// use std::io::Write;
+///
+/// let result: Result<(), String> = ci_group::group_on_error("Fetch", |out| {
+///     writeln!(out, "GET https://example.com").unwrap();
+///     Err("connection refused".into())
+/// });
+/// // The GET line was printed inside a "Fetch" group.
+/// assert!(result.is_err());
+/// ```
+pub fn group_on_error<T, E, F>(title: impl AsRef<str>, f: F) -> Result<T, E>
+where
+    F: FnOnce(&mut Vec<u8>) -> Result<T, E>,
+{
+    group_on_error_with(title, OnSuccess::Print, f)
+}
+
+/// [`group_on_error`] with a choice of what happens to the output on success.
+pub fn group_on_error_with<T, E, F>(
+    title: impl AsRef<str>,
+    on_success: OnSuccess,
+    f: F,
+) -> Result<T, E>
+where
+    F: FnOnce(&mut Vec<u8>) -> Result<T, E>,
+{
+    let mut output = Vec::new();
+    let result = f(&mut output);
+    match (&result, on_success) {
+        (Err(_), _) => {
+            let _group = open(title);
+            if !output.is_empty() {
+                Sink::selected().write_serialized(&output);
+            }
+        }
+        (Ok(_), OnSuccess::Print) if !output.is_empty() => {
+            Sink::selected().write_serialized(&output)
+        }
+        (Ok(_), _) => {}
+    }
+    result
+}
+
+/// Runs a future inside a log group and returns its output.
+///
+/// The group opens when the future is first polled and closes when it completes. The guard lives in
+/// the future's state, so if the future is cancelled (dropped mid-`.await`, e.g. by a timeout or
+/// `select!`) the group still closes at that point. A future dropped before its first poll writes
+/// nothing.
+///
+/// Nesting is tracked per thread, so on a multi-threaded runtime keep grouped futures from
+/// overlapping if you rely on GitHub's flattening.
+///
+/// ```rust
+/// # async fn fetch() -> u32 { 7 }
+/// # async fn run() {
+/// let n = ci_group::grouped("Fetch", fetch()).await;
+/// # }
+/// ```
+#[cfg(feature = "async")]
+pub async fn grouped<F: std::future::Future>(title: impl AsRef<str>, fut: F) -> F::Output {
+    let _guard = open(title);
+    fut.await
+}
+
+/// Opens a log group for the duration of a block and evaluates to the block's value.
+///
+/// The group is closed before the value is handed back:
+///
+/// ```rust
+/// let n = ci_group::group!("Count", {
+///     println!("Counting...");
+///     42
+/// });
+/// assert_eq!(n, 42);
+/// ```
+///
+/// A string literal followed by format arguments and a `;` builds the title with `format!`:
+///
+/// ```rust
+/// let file = "src/lib.rs";
+/// ci_group::group!("Processing {}", file; {
+///     println!("Reading {file}...");
+/// });
+/// ```
+///
+/// `mode = ` and a [`Mode`] variant opens the group with [`open_with`]:
+///
+/// ```rust
+/// ci_group::group!("Build", mode = ForceGitHub, {
+///     println!("Building...");
+/// });
+/// ```
+#[macro_export]
+macro_rules! group {
+    ($fmt:literal $(, $arg:expr)* ; $body:block) => {
+        $crate::group!(::std::format!($fmt $(, $arg)*), $body)
+    };
+    ($title:expr, mode = $mode:ident, $body:block) => {{
+        let guard = $crate::open_with($title, $crate::Mode::$mode);
+        let value = $body;
+        ::core::mem::drop(guard);
+        value
+    }};
+    ($title:literal, $body:block) => {{
+        let guard = $crate::open_static($title);
+        let value = $body;
+        ::core::mem::drop(guard);
+        value
+    }};
+    ($title:expr, $body:block) => {{
+        let guard = $crate::open($title);
+        let value = $body;
+        ::core::mem::drop(guard);
+        value
+    }};
+}
+
+/// Like `println!`, but writes through [`log`] so the line is ordered with the group markers.
+///
+/// ```rust
+/// let _g = ci_group::open("Build");
+/// ci_group::glog!("compiling {} crates", 12);
+/// ```
+#[macro_export]
+macro_rules! glog {
+    ($($arg:tt)*) => {
+        $crate::log(::core::format_args!($($arg)*))
+    };
+}
+
+#[cfg(test)]
+#[allow(deprecated)] // detection tests call the `detect` alias
+mod tests {
+    use super::*;
+    use crate::test_support::{temp_path, with_ci_env, Capture};
+    use crate::{register_provider, CiProvider};
+    use std::sync::mpsc;
+
+    #[test]
+    fn detects_github() {
+        temp_env::with_var("GITHUB_ACTIONS", Some("true"), || {
+            assert_eq!(Provider::detect(), Provider::GitHub);
+        });
+        // CI is set on GitHub's own runners and would select the generic fallback.
+        temp_env::with_vars_unset(["GITHUB_ACTIONS", "CI"], || {
+            assert_eq!(Provider::detect(), Provider::None);
+        });
+    }
+
+    #[test]
+    fn detects_github_case_insensitive() {
+        temp_env::with_var("GITHUB_ACTIONS", Some("TRUE"), || {
+            assert_eq!(Provider::detect(), Provider::GitHub);
+        });
+        temp_env::with_var("GITHUB_ACTIONS", Some("True"), || {
+            assert_eq!(Provider::detect(), Provider::GitHub);
+        });
+    }
+
+    #[test]
+    fn rejects_non_true_values() {
+        temp_env::with_var_unset("CI", || {
+            temp_env::with_var("GITHUB_ACTIONS", Some("false"), || {
+                assert_eq!(Provider::detect(), Provider::None);
+            });
+            temp_env::with_var("GITHUB_ACTIONS", Some(""), || {
+                assert_eq!(Provider::detect(), Provider::None);
+            });
+            temp_env::with_var("GITHUB_ACTIONS", Some("1"), || {
+                assert_eq!(Provider::detect(), Provider::None);
+            });
+        });
+    }
+
+    #[test]
+    fn detects_azure() {
+        // Must unset GITHUB_ACTIONS since this test runs in GitHub Actions
+        temp_env::with_vars(
+            [("TF_BUILD", Some("True")), ("GITHUB_ACTIONS", None)],
+            || {
+                assert_eq!(Provider::detect(), Provider::Azure);
+            },
+        );
+    }
+
+    #[test]
+    fn detects_azure_by_collection_uri() {
+        let uri = (
+            "SYSTEM_TEAMFOUNDATIONCOLLECTIONURI",
+            "https://dev.azure.com/acme/",
+        );
+        with_ci_env(&[uri], || {
+            assert_eq!(Provider::detect(), Provider::Azure);
+        });
+        with_ci_env(&[uri, ("TF_BUILD", "False")], || {
+            assert_eq!(Provider::detect(), Provider::Azure);
+        });
+        with_ci_env(&[("SYSTEM_TEAMFOUNDATIONCOLLECTIONURI", "")], || {
+            assert_eq!(Provider::detect(), Provider::None);
+        });
+    }
+
+    #[test]
+    fn detects_gitlab() {
+        with_ci_env(&[("GITLAB_CI", "true")], || {
+            assert_eq!(Provider::detect(), Provider::GitLab);
+        });
+    }
+
+    #[test]
+    fn detects_circleci() {
+        with_ci_env(&[("CIRCLECI", "true")], || {
+            assert_eq!(Provider::detect(), Provider::CircleCI);
+        });
+        with_ci_env(&[("CIRCLECI", "false")], || {
+            assert_eq!(Provider::detect(), Provider::None);
+        });
+    }
+
+    #[test]
+    fn override_takes_precedence() {
+        with_ci_env(
+            &[("CI_GROUP_PROVIDER", "Azure"), ("GITHUB_ACTIONS", "true")],
+            || {
+                assert_eq!(Provider::detect(), Provider::Azure);
+            },
+        );
+        with_ci_env(
+            &[("CI_GROUP_PROVIDER", "none"), ("GITHUB_ACTIONS", "true")],
+            || {
+                assert_eq!(Provider::detect(), Provider::None);
+            },
+        );
+        with_ci_env(&[("CI_GROUP_PROVIDER", "GITHUB")], || {
+            assert_eq!(Provider::detect(), Provider::GitHub);
+        });
+    }
+
+    #[test]
+    fn names_round_trip_through_the_override() {
+        let all = [
+            (Provider::GitHub, "github"),
+            (Provider::Azure, "azure"),
+            (Provider::GitLab, "gitlab"),
+            (Provider::CircleCI, "circleci"),
+            (Provider::Buildkite, "buildkite"),
+            (Provider::TeamCity, "teamcity"),
+            (Provider::Jenkins, "jenkins"),
+            (Provider::Bitbucket, "bitbucket"),
+            (Provider::Drone, "drone"),
+            (Provider::Harness, "harness"),
+            (Provider::AppVeyor, "appveyor"),
+            (Provider::Semaphore, "semaphore"),
+            (Provider::Generic, "generic"),
+            (Provider::None, "none"),
+        ];
+        for (provider, name) in all {
+            assert_eq!(provider.name(), name);
+            // Real detection picks another provider, so only a parsed override passes.
+            let other = if provider == Provider::GitHub {
+                "CI"
+            } else {
+                "GITHUB_ACTIONS"
+            };
+            with_ci_env(&[("CI_GROUP_PROVIDER", name), (other, "true")], || {
+                assert_eq!(Provider::detect(), provider);
+            });
+        }
+    }
+
+    #[test]
+    fn invalid_override_is_ignored() {
+        with_ci_env(
+            &[
+                ("CI_GROUP_PROVIDER", "jenkins?"),
+                ("GITHUB_ACTIONS", "true"),
+            ],
+            || {
+                assert_eq!(Provider::detect(), Provider::GitHub);
+            },
+        );
+        with_ci_env(&[("CI_GROUP_PROVIDER", "")], || {
+            assert_eq!(Provider::detect(), Provider::None);
+        });
+    }
+
+    #[test]
+    fn detects_buildkite() {
+        with_ci_env(&[("BUILDKITE", "true")], || {
+            assert_eq!(Provider::detect(), Provider::Buildkite);
+        });
+    }
+
+    #[test]
+    fn detects_teamcity_by_presence() {
+        with_ci_env(&[("TEAMCITY_VERSION", "2024.03 (build 156057)")], || {
+            assert_eq!(Provider::detect(), Provider::TeamCity);
+        });
+        with_ci_env(&[("TEAMCITY_VERSION", "false")], || {
+            assert_eq!(Provider::detect(), Provider::TeamCity);
+        });
+        with_ci_env(&[("TEAMCITY_VERSION", "")], || {
+            assert_eq!(Provider::detect(), Provider::TeamCity);
+        });
+    }
+
+    #[test]
+    fn detects_jenkins_by_presence() {
+        with_ci_env(&[("JENKINS_URL", "https://ci.example.com/")], || {
+            assert_eq!(Provider::detect(), Provider::Jenkins);
+        });
+        with_ci_env(&[("JENKINS_URL", "")], || {
+            assert_eq!(Provider::detect(), Provider::None);
+        });
+    }
+
+    #[test]
+    fn detects_bitbucket_by_presence() {
+        with_ci_env(&[("BITBUCKET_BUILD_NUMBER", "42")], || {
+            assert_eq!(Provider::detect(), Provider::Bitbucket);
+        });
+    }
+
+    #[test]
+    fn detects_drone() {
+        with_ci_env(&[("DRONE", "true")], || {
+            assert_eq!(Provider::detect(), Provider::Drone);
+        });
+        with_ci_env(&[("DRONE", "1")], || {
+            assert_eq!(Provider::detect(), Provider::None);
+        });
+    }
+
+    #[test]
+    fn detects_appveyor() {
+        with_ci_env(&[("APPVEYOR", "True"), ("CI", "True")], || {
+            assert_eq!(Provider::detect(), Provider::AppVeyor);
+        });
+    }
+
+    #[test]
+    fn detects_semaphore() {
+        with_ci_env(&[("SEMAPHORE", "true"), ("CI", "true")], || {
+            assert_eq!(Provider::detect(), Provider::Semaphore);
+        });
+    }
+
+    /// GitHub Enterprise Server runs the same runner; only the server URL differs.
+    #[test]
+    fn github_enterprise_server_is_github() {
+        with_ci_env(
+            &[
+                ("GITHUB_ACTIONS", "true"),
+                ("GITHUB_SERVER_URL", "https://github.example.com"),
+            ],
+            || {
+                assert_eq!(Provider::detect(), Provider::GitHub);
+            },
+        );
+    }
+
+    #[test]
+    fn detects_harness_by_presence() {
+        with_ci_env(&[("HARNESS_BUILD_ID", "17")], || {
+            assert_eq!(Provider::detect(), Provider::Harness);
+        });
+    }
+
+    /// GitHub Actions runners can sit inside other orchestrators; its markers win.
+    #[test]
+    fn github_wins_over_drone() {
+        with_ci_env(&[("DRONE", "true"), ("GITHUB_ACTIONS", "true")], || {
+            assert_eq!(Provider::detect(), Provider::GitHub);
+        });
+    }
+
+    #[test]
+    fn generic_ci_is_the_last_resort() {
+        for value in ["true", "woodpecker", "1"] {
+            with_ci_env(&[("CI", value)], || {
+                assert_eq!(Provider::detect(), Provider::Generic, "CI={value}");
+            });
+        }
+        for value in ["", "false", "FALSE", "0"] {
+            with_ci_env(&[("CI", value)], || {
+                assert_eq!(Provider::detect(), Provider::None, "CI={value}");
+            });
+        }
+        with_ci_env(&[("CI", "true"), ("GITLAB_CI", "true")], || {
+            assert_eq!(Provider::detect(), Provider::GitLab);
+        });
+        with_ci_env(&[("CI", "true"), ("HARNESS_BUILD_ID", "1")], || {
+            assert_eq!(Provider::detect(), Provider::Harness);
+        });
+    }
+
+    #[test]
+    fn env_helpers_distinguish_true_from_set() {
+        temp_env::with_vars(
+            [
+                ("CI_GROUP_TEST_TRUE", Some("TRUE")),
+                ("CI_GROUP_TEST_URL", Some("https://ci.example.com/")),
+                ("CI_GROUP_TEST_EMPTY", Some("")),
+                ("CI_GROUP_TEST_UNSET", None),
+            ],
+            || {
+                assert!(env_is_true("CI_GROUP_TEST_TRUE"));
+                assert!(!env_is_true("CI_GROUP_TEST_URL"));
+                assert!(env_is_set("CI_GROUP_TEST_TRUE"));
+                assert!(env_is_set("CI_GROUP_TEST_URL"));
+                assert!(env_is_set("CI_GROUP_TEST_EMPTY"));
+                assert!(!env_is_set("CI_GROUP_TEST_UNSET"));
+                assert!(env_is_non_empty("CI_GROUP_TEST_URL"));
+                assert!(!env_is_non_empty("CI_GROUP_TEST_EMPTY"));
+                assert!(!env_is_non_empty("CI_GROUP_TEST_UNSET"));
+            },
+        );
+    }
+
+    #[test]
+    fn is_active_works() {
+        assert!(Provider::GitHub.is_active());
+        assert!(Provider::Azure.is_active());
+        assert!(Provider::GitLab.is_active());
+        assert!(Provider::CircleCI.is_active());
+        assert!(Provider::Buildkite.is_active());
+        assert!(Provider::TeamCity.is_active());
+        assert!(Provider::Jenkins.is_active());
+        assert!(Provider::Bitbucket.is_active());
+        assert!(Provider::Drone.is_active());
+        assert!(Provider::Harness.is_active());
+        assert!(Provider::AppVeyor.is_active());
+        assert!(Provider::Semaphore.is_active());
+        assert!(Provider::Generic.is_active());
+        assert!(!Provider::None.is_active());
+    }
+
+    #[test]
+    fn writes_markers_to_custom_writer() {
+        let buf = Capture::default();
+        with_ci_env(&[("GITHUB_ACTIONS", "true")], || {
+            let g = Group::with_writer("Build", buf.clone());
+            assert_eq!(buf.contents(), "\n::group::Build\n");
+            drop(g);
+        });
+        assert_eq!(buf.contents(), "\n::group::Build\n\n::endgroup::\n");
+    }
+
+    #[test]
+    fn custom_writer_uses_azure_markers() {
+        let buf = Capture::default();
+        with_ci_env(&[("TF_BUILD", "true")], || {
+            let _g = Group::with_writer("Build", buf.clone());
+        });
+        assert_eq!(buf.contents(), "\n##[group]Build\n\n##[endgroup]\n");
+    }
+
+    #[test]
+    fn custom_writer_silent_outside_ci() {
+        let buf = Capture::default();
+        with_ci_env(&[], || {
+            let _g = Group::with_writer("Build", buf.clone());
+        });
+        assert!(buf.contents().is_empty());
+    }
+
+    #[test]
+    fn gitlab_section_names_are_slugged_and_unique() {
+        let a = gitlab_section_name("Build & Test: api");
+        let b = gitlab_section_name("Build & Test: api");
+        assert!(a.starts_with("Build___Test__api_"));
+        assert_ne!(a, b);
+        assert!(a.chars().all(|c| c.is_ascii_alphanumeric() || c == '_'));
+    }
+
+    #[test]
+    fn writes_gitlab_section_markers() {
+        let buf = Capture::default();
+        with_ci_env(&[("GITLAB_CI", "true")], || {
+            let _g = Group::with_writer("Unit tests", buf.clone());
+        });
+
+        let out = buf.contents();
+        let lines: Vec<&str> = out.lines().filter(|l| !l.is_empty()).collect();
+        assert_eq!(lines.len(), 2);
+
+        let start = lines[0].strip_prefix("\x1b[0Ksection_start:").unwrap();
+        let (ts, rest) = start.split_once(':').unwrap();
+        assert!(ts.parse::<u64>().unwrap() > 0);
+        let (name, title) = rest.split_once("\r\x1b[0K").unwrap();
+        assert!(name.starts_with("Unit_tests_"));
+        assert_eq!(title, "Unit tests");
+
+        let end = lines[1].strip_prefix("\x1b[0Ksection_end:").unwrap();
+        let (_, rest) = end.split_once(':').unwrap();
+        assert_eq!(rest, format!("{name}\r\x1b[0K"));
+    }
+
+    #[test]
+    fn timed_group_reports_duration_after_close() {
+        let buf = Capture::default();
+        with_ci_env(&[("GITHUB_ACTIONS", "true")], || {
+            let _g = Group::with_writer("Build", buf.clone()).timed();
+        });
+
+        let out = buf.contents();
+        let timing = out
+            .strip_prefix("\n::group::Build\n\n::endgroup::\nBuild took ")
+            .unwrap();
+        let secs = timing.strip_suffix("s\n").unwrap();
+        assert!(secs.parse::<f64>().unwrap() >= 0.0);
+    }
+
+    #[test]
+    fn timed_group_uses_azure_command_prefix() {
+        let buf = Capture::default();
+        with_ci_env(&[("TF_BUILD", "true")], || {
+            let _g = Group::with_writer("Build", buf.clone()).timed();
+        });
+        assert!(buf
+            .contents()
+            .contains("\n##[endgroup]\n##[command]Build took "));
+    }
+
+    #[test]
+    fn timed_group_silent_outside_ci() {
+        let buf = Capture::default();
+        with_ci_env(&[], || {
+            let _g = Group::with_writer("Build", buf.clone()).timed();
+        });
+        assert!(buf.contents().is_empty());
+    }
+
+    #[test]
+    fn close_emits_exactly_one_end_marker() {
+        let buf = Capture::default();
+        with_ci_env(&[("GITHUB_ACTIONS", "true")], || {
+            let g = Group::with_writer("Step", buf.clone());
+            g.close();
+            assert_eq!(buf.contents().matches("::endgroup::").count(), 1);
+        });
+        assert_eq!(buf.contents().matches("::endgroup::").count(), 1);
+    }
+
+    #[test]
+    fn circleci_prints_banner() {
+        let buf = Capture::default();
+        with_ci_env(&[("CIRCLECI", "true")], || {
+            let _g = Group::with_writer("Build", buf.clone());
+        });
+        assert_eq!(buf.contents(), "\n----- Build -----\n\n");
+    }
+
+    #[test]
+    fn nested_github_groups_are_flattened() {
+        let buf = Capture::default();
+        with_ci_env(&[("GITHUB_ACTIONS", "true")], || {
+            let _outer = Group::with_writer("Outer", buf.clone());
+            {
+                let _inner = Group::with_writer("Inner", buf.clone());
+            }
+            writeln!(buf.clone(), "still in outer").unwrap();
+        });
+        assert_eq!(
+            buf.contents(),
+            "\n::group::Outer\nstill in outer\n\n::endgroup::\n"
+        );
+    }
+
+    #[test]
+    fn group_writer_opens_on_first_write() {
+        fn emit(out: &mut dyn Write, text: &str) {
+            out.write_all(b"").unwrap();
+            write!(out, "{text}").unwrap();
+            out.flush().unwrap();
+        }
+
+        let buf = Capture::default();
+        with_ci_env(&[("GITHUB_ACTIONS", "true")], || {
+            let mut out = GroupWriter::new("Manifest", buf.clone());
+            assert!(!out.is_open());
+            emit(&mut out, "{\"a\": 1}\n");
+            assert!(out.is_open());
+        });
+        assert_eq!(
+            buf.contents(),
+            "\n::group::Manifest\n{\"a\": 1}\n\n::endgroup::\n"
+        );
+
+        let buf = Capture::default();
+        with_ci_env(&[("GITHUB_ACTIONS", "true")], || {
+            let mut out = GroupWriter::new("Empty", buf.clone());
+            emit(&mut out, "");
+            assert!(!out.is_open());
+            drop(out);
+            assert_eq!(current_depth(), 0);
+        });
+        assert_eq!(buf.contents(), "");
+    }
+
+    #[test]
+    fn github_groups_reopen_after_outer_closes() {
+        let buf = Capture::default();
+        with_ci_env(&[("GITHUB_ACTIONS", "true")], || {
+            drop(Group::with_writer("One", buf.clone()));
+            drop(Group::with_writer("Two", buf.clone()));
+        });
+        assert_eq!(buf.contents().matches("::group::").count(), 2);
+        assert_eq!(buf.contents().matches("::endgroup::").count(), 2);
+    }
+
+    #[test]
+    fn nested_azure_groups_are_kept() {
+        let buf = Capture::default();
+        with_ci_env(&[("TF_BUILD", "true")], || {
+            let _outer = Group::with_writer("Outer", buf.clone());
+            let _inner = Group::with_writer("Inner", buf.clone());
+        });
+        assert_eq!(buf.contents().matches("##[group]").count(), 2);
+    }
+
+    #[test]
+    fn identical_nesting_code_is_valid_on_azure_and_github() {
+        let nested = |env: &[(&str, &str)]| {
+            let buf = Capture::default();
+            with_ci_env(env, || {
+                set_writer(buf.clone());
+                {
+                    let outer = open("Build");
+                    let inner = open("Compile");
+                    assert_eq!(current_depth(), 2);
+                    drop((inner, outer));
+                }
+                take_writer();
+            });
+            buf.contents()
+        };
+
+        let azure = nested(&[("TF_BUILD", "true")]);
+        assert_eq!(azure.matches("##[group]").count(), 2);
+        assert_eq!(azure.matches("##[endgroup]").count(), 2);
+        assert!(azure.find("##[group]Compile") > azure.find("##[group]Build"));
+
+        let github = nested(&[("GITHUB_ACTIONS", "true")]);
+        assert_eq!(github, "\n::group::Build\n\n::endgroup::\n");
+    }
+
+    #[test]
+    fn title_removes_line_breaks_and_keeps_colour_codes() {
+        assert_eq!(
+            Title::from("one\ntwo\r\nthree\rfour").as_str(),
+            "one two three four"
+        );
+        assert_eq!(
+            Title::from("\x1b[32mpassed\x1b[0m").as_str(),
+            "\x1b[32mpassed\x1b[0m"
+        );
+        assert_eq!(
+            Title::from("keeps\ttabs and ünïcode").as_str(),
+            "keeps\ttabs and ünïcode"
+        );
+    }
+
+    #[test]
+    fn title_truncates_and_normalizing_again_is_a_no_op() {
+        with_ci_env(&[], || {
+            let long = format!("{}\n{}", "é".repeat(DEFAULT_MAX_TITLE_LEN), "tail");
+            let title = Title::from(long.as_str());
+            assert!(title.as_str().len() <= DEFAULT_MAX_TITLE_LEN);
+            assert!(title.as_str().ends_with('…'));
+            assert_eq!(Title::from(title.as_str()), title);
+            assert_eq!(open(&title).label(), title.as_str());
+        });
+    }
+
+    #[test]
+    fn title_keeps_github_escaping_for_the_marker() {
+        let buf = Capture::default();
+        with_ci_env(&[("GITHUB_ACTIONS", "true")], || {
+            let title = Title::from("100%\n::endgroup::");
+            assert_eq!(title.as_str(), "100% ::endgroup::");
+            drop(Group::with_writer(&title, buf.clone()));
+        });
+        assert!(buf
+            .contents()
+            .starts_with("\n::group::100%25 ::endgroup::\n"));
+    }
+
+    #[test]
+    fn nested_banner_groups_indent_logged_lines() {
+        let buf = Capture::default();
+        with_ci_env(&[("JENKINS_URL", "x")], || {
+            set_writer(buf.clone());
+            set_indent_logs(true);
+            {
+                let _outer = open("Outer");
+                glog!("one level");
+                let _inner = open("Inner");
+                glog!("two levels\nstill two");
+            }
+            glog!("none");
+            set_indent_logs(false);
+            take_writer();
+        });
+        let out = buf.contents();
+        let logged: Vec<&str> = out
+            .lines()
+            .filter(|l| !l.is_empty() && !l.contains("=========="))
+            .collect();
+        assert_eq!(
+            logged,
+            ["  one level", "    two levels", "    still two", "none"]
+        );
+    }
+
+    #[test]
+    fn native_groups_are_not_indented() {
+        let buf = Capture::default();
+        with_ci_env(&[("TF_BUILD", "true")], || {
+            set_writer(buf.clone());
+            set_indent_logs(true);
+            let _g = open("Build");
+            glog!("flush left");
+            set_indent_logs(false);
+            take_writer();
+        });
+        assert!(buf.contents().contains("\nflush left\n"));
+    }
+
+    /// Warns about the watched groups as if `after` had passed, without waiting for the thread.
+    fn warn_overdue_after(after: Duration) {
+        warn_overdue(&mut watchdogs().0, Instant::now() + after);
+    }
+
+    #[test]
+    fn watchdog_warns_about_a_group_left_open() {
+        let buf = Capture::default();
+        with_ci_env(&[("GITHUB_ACTIONS", "true")], || {
+            set_writer(buf.clone());
+            let g = open_with_timeout("Hang", Duration::from_secs(3600));
+            warn_overdue_after(Duration::from_secs(3600));
+            drop(g);
+            take_writer();
+        });
+        let out = buf.contents();
+        let lines: Vec<&str> = out.lines().filter(|l| !l.is_empty()).collect();
+        assert_eq!(
+            lines,
+            [
+                "::group::Hang",
+                "::warning::Group 'Hang' has been open for 3600.0s",
+                "::endgroup::"
+            ]
+        );
+    }
+
+    #[test]
+    fn watchdog_is_cancelled_when_the_group_closes() {
+        let buf = Capture::default();
+        with_ci_env(&[("GITHUB_ACTIONS", "true")], || {
+            set_writer(buf.clone());
+            drop(open_with_timeout("Quick", Duration::from_secs(3600)));
+            warn_overdue_after(Duration::from_secs(3600));
+            take_writer();
+        });
+        assert!(
+            !buf.contents().contains("::warning::"),
+            "{}",
+            buf.contents()
+        );
+    }
+
+    #[test]
+    fn watchdog_warns_on_the_group_stream() {
+        let buf = Capture::default();
+        let other = Capture::default();
+        with_ci_env(&[("GITHUB_ACTIONS", "true")], || {
+            set_writer(other.clone());
+            let g = GroupBuilder::new()
+                .title("Hang")
+                .stream(OutputStream::Custom(Arc::new(Mutex::new(buf.clone()))))
+                .warn_after(Duration::from_secs(3600))
+                .open();
+            warn_overdue_after(Duration::from_secs(3600));
+            drop(g);
+            take_writer();
+        });
+        assert!(buf.contents().contains("::warning::Group 'Hang'"));
+        assert_eq!(other.contents(), "");
+    }
+
+    #[test]
+    fn watchdog_keeps_every_group_in_one_list() {
+        let buf = Capture::default();
+        with_ci_env(&[("TF_BUILD", "True")], || {
+            set_writer(buf.clone());
+            let groups: Vec<Group> = (0..3)
+                .map(|i| {
+                    GroupBuilder::new()
+                        .title(format!("Step {i}"))
+                        .warn_after(Duration::from_secs(3600))
+                        .open()
+                })
+                .collect();
+            let watched = watchdogs();
+            assert!(watched.1);
+            assert_eq!(watched.0.len(), 3);
+            drop(watched);
+            drop(groups);
+            assert!(watchdogs().0.is_empty());
+            take_writer();
+        });
+    }
+
+    #[test]
+    fn watchdog_ignores_groups_that_write_nothing() {
+        let buf = Capture::default();
+        with_ci_env(&[("GITHUB_ACTIONS", "true")], || {
+            set_writer(buf.clone());
+            let disabled = GroupBuilder::new()
+                .title("Quiet")
+                .mode(Mode::Disabled)
+                .warn_after(Duration::from_secs(3600))
+                .open();
+            let none = GroupBuilder::new()
+                .title("Nowhere")
+                .provider_override(Provider::None)
+                .warn_after(Duration::from_secs(3600))
+                .open();
+            warn_overdue_after(Duration::from_secs(3600));
+            drop((disabled, none));
+            take_writer();
+        });
+        assert_eq!(buf.contents(), "");
+    }
+
+    #[test]
+    fn group_set_closes_in_the_chosen_order() {
+        for (order, expected) in [
+            (CloseOrder::Lifo, ["Three", "Two", "One"]),
+            (CloseOrder::Fifo, ["One", "Two", "Three"]),
+        ] {
+            let buf = Capture::default();
+            with_ci_env(&[("TEAMCITY_VERSION", "1")], || {
+                set_writer(buf.clone());
+                let mut set = GroupSet::new();
+                for title in ["One", "Two", "Three"] {
+                    set.push(title);
+                }
+                assert_eq!(current_depth(), 3);
+                set.close_all(order);
+                assert_eq!(current_depth(), 0);
+                take_writer();
+            });
+            let out = buf.contents();
+            let closed: Vec<&str> = out
+                .lines()
+                .filter_map(|line| line.strip_prefix("##teamcity[blockClosed name='"))
+                .map(|rest| rest.trim_end_matches("']"))
+                .collect();
+            assert_eq!(closed, expected, "{order:?}");
+        }
+    }
+
+    #[test]
+    fn dropped_group_set_closes_newest_first() {
+        let buf = Capture::default();
+        with_ci_env(&[("TF_BUILD", "true")], || {
+            let mut set = GroupSet::new();
+            set.push_group(Group::with_writer("Outer", buf.clone()));
+            set.push_group(Group::with_writer("Inner", buf.clone()));
+            let _after = Group::with_writer("Unrelated", buf.clone());
+            drop(set);
+            assert_eq!(active_titles(), ["Unrelated"]);
+        });
+        assert_eq!(
+            buf.contents(),
+            "\n##[group]Outer\n\n##[group]Inner\n\n##[group]Unrelated\n\n##[endgroup]\n\n##[endgroup]\n\n##[endgroup]\n"
+        );
+    }
+
+    #[test]
+    fn only_providers_that_need_it_write_a_close_marker() {
+        for (var, close) in [
+            ("GITHUB_ACTIONS", Some("::endgroup::")),
+            ("TF_BUILD", Some("##[endgroup]")),
+            ("BUILDKITE", None),
+        ] {
+            let buf = Capture::default();
+            with_ci_env(&[(var, "true")], || {
+                let g = Group::with_writer("Build", buf.clone());
+                let opened = buf.contents();
+                drop(g);
+                let closed = buf.contents()[opened.len()..].to_owned();
+                assert_eq!(closed.lines().find(|l| !l.is_empty()), close, "{var}");
+                assert_eq!(detected_provider().needs_explicit_close(), close.is_some());
+            });
+        }
+    }
+
+    #[test]
+    fn last_marker_follows_open_and_close() {
+        thread::spawn(|| {
+            assert_eq!(last_marker(), None);
+            with_ci_env(&[("TF_BUILD", "true")], || {
+                let g = Group::with_writer("Build", std::io::sink());
+                assert_eq!(last_marker().as_deref(), Some("##[group]Build"));
+                drop(g);
+                assert_eq!(last_marker().as_deref(), Some("##[endgroup]"));
+            });
+        })
+        .join()
+        .unwrap();
+    }
+
+    #[test]
+    fn dry_run_records_nested_groups_and_writes_nothing() {
+        let writer = Capture::default();
+        let events = with_ci_env(&[("GITHUB_ACTIONS", "true")], || {
+            set_writer(writer.clone());
+            {
+                let _outer = open_with("Build", Mode::DryRun);
+                let mut inner = open_with("Compile", Mode::DryRun);
+                assert!(!inner.is_real());
+                inner.set_title("Compiled");
+            }
+            take_writer();
+            take_recorded_events()
+        });
+        let event = |action, title: &str| GroupEvent {
+            action,
+            provider: Provider::GitHub,
+            title: title.to_owned(),
+        };
+        assert_eq!(
+            events,
+            [
+                event(GroupAction::Open, "Build"),
+                event(GroupAction::Open, "Compile"),
+                event(GroupAction::Close, "Compiled"),
+                event(GroupAction::Close, "Build"),
+            ]
+        );
+        assert_eq!(writer.contents(), "");
+        assert!(take_recorded_events().is_empty());
+    }
+
+    #[test]
+    fn dry_run_records_the_overridden_provider() {
+        let events = with_ci_env(&[], || {
+            drop(
+                GroupBuilder::new()
+                    .title("Publish")
+                    .mode(Mode::DryRun)
+                    .provider_override(Provider::Azure)
+                    .open(),
+            );
+            take_recorded_events()
+        });
+        assert_eq!(events.len(), 2);
+        assert!(events.iter().all(|event| event.provider == Provider::Azure));
+    }
+
+    #[test]
+    fn title_prefix_appears_in_the_marker() {
+        let buf = Capture::default();
+        with_ci_env(&[("GITHUB_ACTIONS", "true")], || {
+            set_title_prefix("[api] ");
+            drop(Group::with_writer("Build", buf.clone()));
+            set_title_prefix("");
+        });
+        assert!(buf.contents().starts_with("\n::group::[api] Build\n"));
+    }
+
+    #[test]
+    fn scoped_title_prefix_is_removed_when_its_guard_drops() {
+        let buf = Capture::default();
+        with_ci_env(&[("TF_BUILD", "true")], || {
+            {
+                let _outer = with_title_prefix("[web] ");
+                {
+                    let _inner = with_title_prefix("[web/ui] ");
+                    drop(Group::with_writer("Lint", buf.clone()));
+                }
+                drop(Group::with_writer("Build", buf.clone()));
+            }
+            drop(Group::with_writer("Deploy", buf.clone()));
+        });
+        let out = buf.contents();
+        let opened: Vec<&str> = out
+            .lines()
+            .filter_map(|line| line.strip_prefix("##[group]"))
+            .collect();
+        assert_eq!(opened, ["[web/ui] Lint", "[web] Build", "Deploy"]);
+    }
+
+    #[test]
+    fn discarded_buffered_group_writes_nothing() {
+        for (var, value) in [
+            ("GITHUB_ACTIONS", "true"),
+            ("TF_BUILD", "true"),
+            ("GITLAB_CI", "true"),
+        ] {
+            let writer = Capture::default();
+            with_ci_env(&[(var, value)], || {
+                set_writer(writer.clone());
+                let mut g = GroupBuilder::new().title("Retry").buffered(true).open();
+                writeln!(g, "attempt 2").unwrap();
+                g.timed().discard();
+                take_writer();
+            });
+            assert_eq!(writer.contents(), "", "{var}");
+        }
+    }
+
+    #[test]
+    fn buffered_group_writes_everything_on_close() {
+        let writer = Capture::default();
+        with_ci_env(&[("GITHUB_ACTIONS", "true")], || {
+            set_writer(writer.clone());
+            let mut g = GroupBuilder::new().title("Build").buffered(true).open();
+            writeln!(g, "compiling").unwrap();
+            assert_eq!(writer.contents(), "");
+            drop(g);
+            take_writer();
+        });
+        assert_eq!(
+            writer.contents(),
+            "\n::group::Build\ncompiling\n\n::endgroup::\n"
+        );
+    }
+
+    #[test]
+    fn discard_without_buffering_skips_the_close_marker() {
+        let buf = Capture::default();
+        with_ci_env(&[("TF_BUILD", "true")], || {
+            Group::with_writer("Speculative", buf.clone()).discard();
+            assert_eq!(current_depth(), 0);
+        });
+        assert_eq!(buf.contents(), "\n##[group]Speculative\n");
+    }
+
+    #[test]
+    fn max_depth_one_flattens_nested_azure_groups() {
+        let buf = Capture::default();
+        with_ci_env(&[("TF_BUILD", "true")], || {
+            set_max_depth(Some(1));
+            {
+                let _outer = Group::with_writer("Outer", buf.clone());
+                let inner = Group::with_writer("Inner", buf.clone());
+                assert!(!inner.is_real());
+                assert_eq!(current_depth(), 2);
+            }
+            set_max_depth(None);
+        });
+        assert_eq!(buf.contents(), "\n##[group]Outer\n\n##[endgroup]\n");
+    }
+
+    #[test]
+    fn max_depth_two_suppresses_the_third_level() {
+        let buf = Capture::default();
+        with_ci_env(&[("TF_BUILD", "true")], || {
+            set_max_depth(Some(2));
+            {
+                let _one = Group::with_writer("One", buf.clone());
+                let _two = Group::with_writer("Two", buf.clone());
+                let _three = Group::with_writer("Three", buf.clone());
+            }
+            let _after = Group::with_writer("After", buf.clone());
+            set_max_depth(None);
+        });
+        let out = buf.contents();
+        assert_eq!(out.matches("##[group]").count(), 3, "{out}");
+        assert_eq!(out.matches("##[endgroup]").count(), 3, "{out}");
+        assert!(!out.contains("Three"));
+        assert!(out.contains("##[group]After"));
+    }
+
+    #[test]
+    fn max_depth_lets_github_groups_nest() {
+        let buf = Capture::default();
+        with_ci_env(&[("GITHUB_ACTIONS", "true")], || {
+            set_max_depth(Some(2));
+            {
+                let _outer = Group::with_writer("Outer", buf.clone());
+                let _inner = Group::with_writer("Inner", buf.clone());
+            }
+            set_max_depth(None);
+        });
+        assert_eq!(buf.contents().matches("::group::").count(), 2);
+    }
+
+    #[test]
+    fn multiline_title_stays_on_marker_line() {
+        for (var, prefix) in [("GITHUB_ACTIONS", "::group::"), ("TF_BUILD", "##[group]")] {
+            let buf = Capture::default();
+            with_ci_env(&[(var, "true")], || {
+                let _g = Group::with_writer("line1\nline2\r\nline3", buf.clone());
+            });
+            let out = buf.contents();
+            assert!(out
+                .lines()
+                .any(|l| l == format!("{prefix}line1 line2 line3")));
+            assert!(!out.lines().any(|l| l.starts_with("line")));
+        }
+    }
+
+    #[test]
+    fn global_writer_captures_markers() {
+        let buf = Capture::default();
+        with_ci_env(&[("GITHUB_ACTIONS", "true")], || {
+            set_writer(buf.clone());
+            drop(open("Global"));
+            assert!(take_writer().is_some());
+        });
+        assert_eq!(buf.contents(), "\n::group::Global\n\n::endgroup::\n");
+        assert!(take_writer().is_none());
+    }
+
+    #[test]
+    fn failing_writer_is_ignored() {
+        struct Broken;
+        impl Write for Broken {
+            fn write(&mut self, _: &[u8]) -> std::io::Result<usize> {
+                Err(std::io::ErrorKind::BrokenPipe.into())
+            }
+            fn flush(&mut self) -> std::io::Result<()> {
+                Err(std::io::ErrorKind::BrokenPipe.into())
+            }
+        }
+        with_ci_env(&[("GITHUB_ACTIONS", "true")], || {
+            drop(Group::with_writer("Broken", Broken).timed());
+        });
+    }
+
+    #[test]
+    fn mirrors_groups_to_log_file() {
+        let path = temp_path("mirror-log");
+        with_ci_env(&[("GITHUB_ACTIONS", "true")], || {
+            temp_env::with_var("CI_GROUP_LOG", Some(&path), || {
+                set_writer(std::io::sink());
+                drop(open("Build \"api\""));
+                take_writer();
+            });
+        });
+        let log = std::fs::read_to_string(&path).unwrap();
+        let lines: Vec<&str> = log.lines().collect();
+        assert!(lines.iter().any(|l| l.starts_with('[')
+            && l.ends_with(r#"] OPEN provider=github title="Build \"api\"""#)));
+        assert!(lines
+            .iter()
+            .any(|l| l.ends_with(r#"] CLOSE title="Build \"api\"""#)));
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    /// Takes `budget` bytes, fails the next write, then works normally again.
+    struct FailsAfter {
+        inner: Capture,
+        budget: usize,
+        failed: bool,
+    }
+
+    impl Write for FailsAfter {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            if self.failed || buf.len() <= self.budget {
+                self.budget = self.budget.saturating_sub(buf.len());
+                return self.inner.write(buf);
+            }
+            if self.budget == 0 {
+                self.failed = true;
+                return Err(std::io::ErrorKind::Other.into());
+            }
+            let n = self.budget;
+            self.budget = 0;
+            self.inner.write(&buf[..n])
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Err(std::io::ErrorKind::Other.into())
+        }
+    }
+
+    #[test]
+    fn recovers_from_a_partial_write() {
+        let buf = Capture::default();
+        with_ci_env(&[("GITHUB_ACTIONS", "true")], || {
+            set_newline_guard(NewlineGuard::WhenNeeded);
+            set_writer(FailsAfter {
+                inner: buf.clone(),
+                budget: 5,
+                failed: false,
+            });
+            drop(open("Cut"));
+            drop(open("Next"));
+            take_writer();
+            set_newline_guard(NewlineGuard::Always);
+        });
+        // The open marker is cut after 5 bytes, so the end marker still breaks the line.
+        assert_eq!(
+            buf.contents(),
+            "\n::gr\n::endgroup::\n::group::Next\n::endgroup::\n"
+        );
+    }
+
+    #[test]
+    fn group_each_closes_every_group_before_the_next() {
+        let buf = Capture::default();
+        with_ci_env(&[("TF_BUILD", "True")], || {
+            set_writer(buf.clone());
+            group_each(1..=3, |n| format!("Item {n}"), |n| glog!("processing {n}"));
+            take_writer();
+        });
+        let lines: Vec<String> = buf
+            .contents()
+            .lines()
+            .filter(|l| !l.is_empty())
+            .map(String::from)
+            .collect();
+        let expected: Vec<String> = (1..=3)
+            .flat_map(|n| {
+                [
+                    format!("##[group]Item {n}"),
+                    format!("processing {n}"),
+                    String::from("##[endgroup]"),
+                ]
+            })
+            .collect();
+        assert_eq!(lines, expected);
+    }
+
+    #[test]
+    fn group_each_closes_the_current_group_on_panic() {
+        let buf = Capture::default();
+        with_ci_env(&[("TF_BUILD", "True")], || {
+            set_writer(buf.clone());
+            let result = std::panic::catch_unwind(|| {
+                group_each(["ok", "boom", "never"], |s| *s, |s| assert_ne!(s, "boom"));
+            });
+            take_writer();
+            assert!(result.is_err());
+            assert_eq!(current_depth(), 0);
+        });
+        let out = buf.contents();
+        assert_eq!(out.matches("##[group]").count(), 2);
+        assert_eq!(out.matches("##[endgroup]").count(), 2);
+        assert!(!out.contains("never"));
+    }
+
+    #[test]
+    fn glog_lines_land_inside_the_group() {
+        let buf = Capture::default();
+        with_ci_env(&[("GITHUB_ACTIONS", "true")], || {
+            set_writer(buf.clone());
+            let guard = open("Logged");
+            glog!("step {}", 1);
+            drop(guard);
+            glog!("after");
+            take_writer();
+        });
+        let out = buf.contents();
+        let open = out.find("::group::Logged\n").unwrap();
+        let line = out.find("\nstep 1\n").unwrap();
+        let close = out.find("::endgroup::").unwrap();
+        assert!(open < line && line < close);
+        assert!(out.ends_with("::endgroup::\nafter\n"));
+    }
+
+    #[test]
+    fn glog_does_not_add_a_line_for_a_trailing_newline() {
+        let buf = Capture::default();
+        with_ci_env(&[("JENKINS_URL", "x")], || {
+            set_writer(buf.clone());
+            set_indent_logs(true);
+            let g = open("Outer");
+            glog!("done\n");
+            glog!("two\nlines\n");
+            drop(g);
+            set_indent_logs(false);
+            take_writer();
+        });
+        let out = buf.contents();
+        assert!(out.contains("\n  done\n  two\n  lines\n\n===="), "{out:?}");
+    }
+
+    #[test]
+    fn glog_prints_plainly_outside_ci() {
+        let buf = Capture::default();
+        with_ci_env(&[], || {
+            set_writer(buf.clone());
+            let _g = open("Quiet");
+            glog!("plain");
+            take_writer();
+        });
+        assert_eq!(buf.contents(), "plain\n");
+    }
+
+    #[test]
+    fn global_writer_is_shared_across_threads() {
+        let buf = Capture::default();
+        with_ci_env(&[("GITHUB_ACTIONS", "true")], || {
+            set_writer(buf.clone());
+            let handles: Vec<_> = (0..4)
+                .map(|i| std::thread::spawn(move || drop(open(format!("Thread {i}")))))
+                .collect();
+            for handle in handles {
+                handle.join().unwrap();
+            }
+            take_writer();
+        });
+        let out = buf.contents();
+        assert_eq!(out.matches("::group::Thread ").count(), 4);
+        assert_eq!(out.matches("::endgroup::").count(), 4);
+    }
+
+    #[test]
+    fn detection_is_cached_until_reset() {
+        with_ci_env(&[("GITHUB_ACTIONS", "true")], || {
+            assert_eq!(Provider::current(), Provider::GitHub);
+            temp_env::with_var("GITHUB_ACTIONS", None::<&str>, || {
+                assert_eq!(Provider::current(), Provider::GitHub);
+                reset_provider_cache();
+                assert_eq!(Provider::current(), Provider::None);
+            });
+        });
+    }
+
+    #[test]
+    fn detected_provider_follows_env_after_reset() {
+        with_ci_env(&[("TF_BUILD", "True")], || {
+            assert_eq!(detected_provider(), Provider::Azure);
+            temp_env::with_vars([("TF_BUILD", None), ("GITLAB_CI", Some("true"))], || {
+                assert_eq!(detected_provider(), Provider::Azure);
+                reset_provider_cache();
+                assert_eq!(detected_provider(), Provider::GitLab);
+            });
+        });
+    }
+
+    #[test]
+    fn buildkite_headers_have_no_end_marker() {
+        let buf = Capture::default();
+        with_ci_env(&[("BUILDKITE", "true")], || {
+            let _g = Group::with_writer("Build", buf.clone());
+        });
+        assert_eq!(buf.contents(), "\n--- Build\n");
+    }
+
+    #[test]
+    fn buildkite_expanded_uses_plus_header() {
+        let buf = Capture::default();
+        with_ci_env(&[("BUILDKITE", "true")], || {
+            set_writer(buf.clone());
+            drop(GroupBuilder::new().title("Collapsed").open());
+            drop(GroupBuilder::new().title("Expanded").expanded(true).open());
+            take_writer();
+        });
+        assert_eq!(buf.contents(), "\n--- Collapsed\n\n+++ Expanded\n");
+    }
+
+    #[test]
+    fn azure_expanded_groups_are_sections() {
+        let buf = Capture::default();
+        with_ci_env(&[("TF_BUILD", "true")], || {
+            set_writer(buf.clone());
+            drop(GroupBuilder::new().title("Collapsed").open());
+            drop(GroupBuilder::new().title("Expanded").expanded(true).open());
+            take_writer();
+        });
+        assert_eq!(
+            buf.contents(),
+            "\n##[group]Collapsed\n\n##[endgroup]\n\n##[section]Expanded\n"
+        );
+    }
+
+    /// A section has no end marker: an `##[endgroup]` for it would close the group around it.
+    #[test]
+    fn azure_section_inside_a_group_leaves_it_open() {
+        let buf = Capture::default();
+        with_ci_env(&[("TF_BUILD", "true")], || {
+            set_writer(buf.clone());
+            {
+                let _outer = open("Build");
+                let section = GroupBuilder::new().title("Results").expanded(true).open();
+                assert!(section.is_real());
+                assert_eq!(current_depth(), 2);
+                drop(section);
+                glog!("still in Build");
+            }
+            take_writer();
+        });
+        let out = buf.contents();
+        let lines: Vec<&str> = out.lines().filter(|l| !l.is_empty()).collect();
+        assert_eq!(
+            lines,
+            [
+                "##[group]Build",
+                "##[section]Results",
+                "still in Build",
+                "##[endgroup]",
+            ]
+        );
+    }
+
+    #[test]
+    fn github_expanded_groups_are_banners_that_groups_can_open_inside() {
+        let buf = Capture::default();
+        with_ci_env(&[("GITHUB_ACTIONS", "true")], || {
+            set_writer(buf.clone());
+            {
+                let _outer = GroupBuilder::new().title("Deploy").expanded(true).open();
+                let _inner = open("Upload");
+            }
+            take_writer();
+        });
+        let out = buf.contents();
+        let lines: Vec<&str> = out.lines().filter(|l| !l.is_empty()).collect();
+        assert_eq!(
+            lines,
+            [
+                "========== Deploy ==========",
+                "::group::Upload",
+                "::endgroup::",
+                "========== end Deploy ==========",
+            ]
+        );
+    }
+
+    #[test]
+    fn disable_switch_silences_every_group() {
+        for value in ["true", "1", "yes"] {
+            let env = [("CI_GROUP_DISABLE", value), ("GITHUB_ACTIONS", "true")];
+            with_ci_env(&env, || {
+                assert_eq!(detected_provider(), Provider::None);
+                let out = Capture::default();
+                set_writer(out.clone());
+                drop(open("Build"));
+                drop(open_with("Forced", Mode::ForceGitHub));
+                drop(open_with("Banner", Mode::LocalBanner));
+                drop(
+                    GroupBuilder::new()
+                        .title("Override")
+                        .provider_override(Provider::Azure)
+                        .open(),
+                );
+                take_writer();
+                assert_eq!(out.contents(), "");
+            });
+        }
+        for env in [
+            &[("CI_GROUP_DISABLE", "false"), ("GITHUB_ACTIONS", "true")][..],
+            &[("CI_GROUP_DISABLE", "0"), ("GITHUB_ACTIONS", "true")][..],
+            &[("GITHUB_ACTIONS", "true")][..],
+        ] {
+            with_ci_env(env, || {
+                assert_eq!(detected_provider(), Provider::GitHub);
+                let out = Capture::default();
+                drop(Group::with_writer("Build", out.clone()));
+                assert_eq!(out.contents(), "\n::group::Build\n\n::endgroup::\n");
+            });
+        }
+    }
+
+    #[test]
+    fn github_groups_are_banners_under_act() {
+        let buf = Capture::default();
+        with_ci_env(&[("GITHUB_ACTIONS", "true"), ("ACT", "true")], || {
+            assert_eq!(detected_provider(), Provider::GitHub);
+            assert!(!supports_folding());
+            set_writer(buf.clone());
+            {
+                let outer = open("Build");
+                let _inner = open("Link");
+                assert_eq!(outer.provider(), Provider::GitHub);
+            }
+            take_writer();
+        });
+        let out = buf.contents();
+        let lines: Vec<&str> = out.lines().filter(|l| !l.is_empty()).collect();
+        assert_eq!(
+            lines,
+            [
+                "========== Build ==========",
+                "========== Link ==========",
+                "========== end Link ==========",
+                "========== end Build ==========",
+            ]
+        );
+        with_ci_env(&[("GITHUB_ACTIONS", "true")], || {
+            assert!(supports_folding());
+            let out = Capture::default();
+            drop(Group::with_writer("Build", out.clone()));
+            assert_eq!(out.contents(), "\n::group::Build\n\n::endgroup::\n");
+        });
+    }
+
+    #[test]
+    fn mode_auto_follows_detection() {
+        let buf = Capture::default();
+        with_ci_env(&[("TF_BUILD", "true")], || {
+            set_writer(buf.clone());
+            drop(open_with("Auto", Mode::Auto));
+            take_writer();
+        });
+        assert_eq!(buf.contents(), "\n##[group]Auto\n\n##[endgroup]\n");
+    }
+
+    #[test]
+    fn forced_modes_ignore_environment() {
+        let buf = Capture::default();
+        with_ci_env(&[], || {
+            set_writer(buf.clone());
+            drop(open_with("GitHub", Mode::ForceGitHub));
+            drop(open_with("Azure", Mode::ForceAzure));
+            take_writer();
+        });
+        assert_eq!(
+            buf.contents(),
+            "\n::group::GitHub\n\n::endgroup::\n\n##[group]Azure\n\n##[endgroup]\n"
+        );
+    }
+
+    #[test]
+    fn disabled_mode_is_silent_in_ci() {
+        let buf = Capture::default();
+        with_ci_env(&[("GITHUB_ACTIONS", "true")], || {
+            set_writer(buf.clone());
+            drop(open_with("Quiet", Mode::Disabled));
+            take_writer();
+        });
+        assert!(buf.contents().is_empty());
+    }
+
+    #[cfg(feature = "async")]
+    fn block_on<F: std::future::Future>(fut: F) -> F::Output {
+        tokio::runtime::Builder::new_current_thread()
+            .enable_time()
+            .build()
+            .unwrap()
+            .block_on(fut)
+    }
+
+    #[cfg(feature = "async")]
+    #[test]
+    fn grouped_wraps_future_output() {
+        let buf = Capture::default();
+        let n = with_ci_env(&[("GITHUB_ACTIONS", "true")], || {
+            set_writer(buf.clone());
+            let n = block_on(grouped("Fetch", async {
+                tokio::task::yield_now().await;
+                writeln!(buf.clone(), "fetching").unwrap();
+                7
+            }));
+            take_writer();
+            n
+        });
+        assert_eq!(n, 7);
+        assert_eq!(
+            buf.contents(),
+            "\n::group::Fetch\nfetching\n\n::endgroup::\n"
+        );
+    }
+
+    #[cfg(feature = "async")]
+    #[test]
+    fn grouped_closes_when_cancelled() {
+        use std::time::Duration;
+
+        let buf = Capture::default();
+        with_ci_env(&[("GITHUB_ACTIONS", "true")], || {
+            set_writer(buf.clone());
+            let result = block_on(async {
+                let slow = grouped("Slow", tokio::time::sleep(Duration::from_secs(60)));
+                tokio::time::timeout(Duration::from_millis(10), slow).await
+            });
+            assert!(result.is_err());
+            take_writer();
+        });
+        assert_eq!(buf.contents(), "\n::group::Slow\n\n::endgroup::\n");
+    }
+
+    #[test]
+    fn writes_teamcity_blocks() {
+        let buf = Capture::default();
+        with_ci_env(&[("TEAMCITY_VERSION", "2024.03")], || {
+            let _g = Group::with_writer("Build [x86] 'fast'", buf.clone());
+        });
+        assert_eq!(
+            buf.contents(),
+            "\n##teamcity[blockOpened name='Build |[x86|] |'fast|'']\n\
+             \n##teamcity[blockClosed name='Build |[x86|] |'fast|'']\n"
+        );
+    }
+
+    #[test]
+    fn on_close_output_lands_inside_group() {
+        let buf = Capture::default();
+        with_ci_env(&[("GITHUB_ACTIONS", "true")], || {
+            let mut summary = buf.clone();
+            let _g = Group::with_writer("Lint", buf.clone())
+                .on_close(move || writeln!(summary, "3 warnings").unwrap());
+            writeln!(buf.clone(), "linting").unwrap();
+        });
+        assert_eq!(
+            buf.contents(),
+            "\n::group::Lint\nlinting\n3 warnings\n\n::endgroup::\n"
+        );
+    }
+
+    #[test]
+    fn on_close_runs_during_panic() {
+        let buf = Capture::default();
+        with_ci_env(&[("GITHUB_ACTIONS", "true")], || {
+            let mut summary = buf.clone();
+            let writer = buf.clone();
+            let result = std::panic::catch_unwind(move || {
+                let _g = Group::with_writer("Boom", writer)
+                    .on_close(move || writeln!(summary, "summary").unwrap());
+                panic!("intentional");
+            });
+            assert!(result.is_err());
+        });
+        assert_eq!(buf.contents(), "\n::group::Boom\nsummary\n\n::endgroup::\n");
+    }
+
+    #[test]
+    fn builder_defaults_match_open() {
+        let buf = Capture::default();
+        with_ci_env(&[("GITHUB_ACTIONS", "true")], || {
+            set_writer(buf.clone());
+            let g = GroupBuilder::new().title("Build").open();
+            assert!(!g.is_expanded());
+            drop(g);
+            drop(open("Build"));
+            take_writer();
+        });
+        assert_eq!(
+            buf.contents(),
+            "\n::group::Build\n\n::endgroup::\n\n::group::Build\n\n::endgroup::\n"
+        );
+    }
+
+    #[test]
+    fn builder_provider_override() {
+        let buf = Capture::default();
+        with_ci_env(&[("GITHUB_ACTIONS", "true")], || {
+            set_writer(buf.clone());
+            let g = GroupBuilder::new()
+                .title("Build")
+                .expanded(true)
+                .provider_override(Provider::Buildkite)
+                .mode(Mode::Disabled)
+                .open();
+            assert!(g.is_expanded());
+            drop(g);
+            take_writer();
+        });
+        assert_eq!(buf.contents(), "\n+++ Build\n");
+    }
+
+    #[test]
+    fn jenkins_prints_header_and_footer() {
+        let buf = Capture::default();
+        with_ci_env(&[("JENKINS_URL", "https://ci.example.com/")], || {
+            let _g = Group::with_writer("Build", buf.clone());
+        });
+        assert_eq!(
+            buf.contents(),
+            "\n========== Build ==========\n\n========== end Build ==========\n"
+        );
+    }
+
+    #[test]
+    fn local_banner_mode_prints_outside_ci() {
+        let buf = Capture::default();
+        with_ci_env(&[], || {
+            set_writer(buf.clone());
+            drop(open_with("Build", Mode::LocalBanner));
+            drop(open_with("Quiet", Mode::Auto));
+            take_writer();
+        });
+        assert_eq!(buf.contents(), "▶ Build\n◀ (done)\n");
+    }
+
+    #[test]
+    fn json_events_mode_writes_a_line_per_event() {
+        let buf = Capture::default();
+        with_ci_env(&[("GITHUB_ACTIONS", "true")], || {
+            set_writer(buf.clone());
+            let outer = open_with("Build", Mode::JsonEvents);
+            drop(open_with("Unit \"tests\"\t", Mode::JsonEvents));
+            drop(outer);
+            take_writer();
+        });
+        let events: Vec<serde_json::Value> = buf
+            .contents()
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        let summary: Vec<(&str, &str, u64)> = events
+            .iter()
+            .map(|e| {
+                assert!(e["ts"].as_u64().unwrap() > 0);
+                let depth = e["depth"].as_u64().unwrap();
+                (
+                    e["event"].as_str().unwrap(),
+                    e["title"].as_str().unwrap(),
+                    depth,
+                )
+            })
+            .collect();
+        assert_eq!(
+            summary,
+            [
+                ("open", "Build", 1),
+                ("open", "Unit \"tests\"\t", 2),
+                ("close", "Unit \"tests\"\t", 2),
+                ("close", "Build", 1),
+            ]
+        );
+    }
+
+    #[test]
+    fn local_banner_mode_defers_to_ci() {
+        let buf = Capture::default();
+        with_ci_env(&[("GITHUB_ACTIONS", "true")], || {
+            set_writer(buf.clone());
+            drop(open_with("Build", Mode::LocalBanner));
+            take_writer();
+        });
+        assert_eq!(buf.contents(), "\n::group::Build\n\n::endgroup::\n");
+    }
+
+    /// Accepts one byte per `write` call, yielding in between, to provoke interleaving.
+    struct Trickle(Capture);
+
+    impl Write for Trickle {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            std::thread::yield_now();
+            self.0.write(&buf[..buf.len().min(1)])
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    /// Takes a few bytes per `write` and fails every third call with `Interrupted`, like a pipe
+    /// under signal pressure.
+    struct Interrupting(Capture, Arc<AtomicUsize>);
+
+    impl Write for Interrupting {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            std::thread::yield_now();
+            if self.1.fetch_add(1, Ordering::Relaxed).is_multiple_of(3) {
+                return Err(std::io::ErrorKind::Interrupted.into());
+            }
+            self.0.write(&buf[..buf.len().min(7)])
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            if self.1.fetch_add(1, Ordering::Relaxed).is_multiple_of(3) {
+                return Err(std::io::ErrorKind::Interrupted.into());
+            }
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn concurrent_banners_survive_short_and_interrupted_writes() {
+        let buf = Capture::default();
+        let calls = Arc::new(AtomicUsize::new(0));
+        let padding = "x".repeat(3000);
+        with_ci_env(&[("JENKINS_URL", "x")], || {
+            let handles: Vec<_> = (0..8)
+                .map(|t| {
+                    let (buf, calls, padding) = (buf.clone(), Arc::clone(&calls), padding.clone());
+                    std::thread::spawn(move || {
+                        for i in 0..10 {
+                            let writer = Interrupting(buf.clone(), Arc::clone(&calls));
+                            drop(Group::with_writer(format!("T{t}-{i} {padding}"), writer));
+                        }
+                    })
+                })
+                .collect();
+            for handle in handles {
+                handle.join().unwrap();
+            }
+        });
+
+        let out = buf.contents();
+        let lines: Vec<&str> = out.lines().filter(|l| !l.is_empty()).collect();
+        assert_eq!(lines.len(), 8 * 10 * 2);
+        let mut titles = std::collections::HashMap::new();
+        for line in lines {
+            let banner = line
+                .strip_prefix("========== ")
+                .and_then(|rest| rest.strip_suffix(" =========="))
+                .filter(|banner| banner.ends_with(&padding));
+            let Some(banner) = banner else {
+                panic!("torn banner: {:?}", &line[..line.len().min(40)]);
+            };
+            let title = banner.strip_prefix("end ").unwrap_or(banner);
+            *titles.entry(title.to_owned()).or_insert(0) += 1;
+        }
+        assert_eq!(titles.len(), 8 * 10);
+        assert!(titles.values().all(|&count| count == 2));
+    }
+
+    #[test]
+    fn concurrent_markers_never_interleave() {
+        let buf = Capture::default();
+        with_ci_env(&[("GITHUB_ACTIONS", "true")], || {
+            let handles: Vec<_> = (0..8)
+                .map(|t| {
+                    let buf = buf.clone();
+                    std::thread::spawn(move || {
+                        for i in 0..20 {
+                            let g = Group::with_writer(format!("T{t}-{i}"), Trickle(buf.clone()));
+                            drop(g);
+                        }
+                    })
+                })
+                .collect();
+            for handle in handles {
+                handle.join().unwrap();
+            }
+        });
+
+        let out = buf.contents();
+        let lines: Vec<&str> = out.lines().filter(|l| !l.is_empty()).collect();
+        assert_eq!(lines.len(), 8 * 20 * 2);
+        for t in 0..8 {
+            for i in 0..20 {
+                let open = format!("::group::T{t}-{i}");
+                assert_eq!(lines.iter().filter(|l| **l == open).count(), 1, "{open}");
+            }
+        }
+        for line in lines {
+            assert!(
+                line == "::endgroup::" || line.starts_with("::group::T"),
+                "torn line: {line:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn bitbucket_prints_header_and_footer() {
+        let buf = Capture::default();
+        with_ci_env(&[("BITBUCKET_BUILD_NUMBER", "42")], || {
+            let _g = Group::with_writer("Deploy", buf.clone());
+        });
+        assert_eq!(
+            buf.contents(),
+            "\n========== Deploy ==========\n\n========== end Deploy ==========\n"
+        );
+    }
+
+    #[test]
+    fn label_returns_the_sanitized_title() {
+        with_ci_env(&[], || {
+            assert_eq!(open("X").label(), "X");
+            assert_eq!(open("line one\r\nline two").label(), "line one line two");
+        });
+    }
+
+    #[test]
+    fn group_is_send() {
+        fn assert_send<T: Send>() {}
+        assert_send::<Group>();
+        assert_send::<GroupBuilder>();
+        assert_send::<TitlePrefixGuard>();
+    }
+
+    #[test]
+    fn depth_tracks_nested_scopes() {
+        with_ci_env(&[], || {
+            assert_eq!(current_depth(), 0);
+            let _outer = open("Outer");
+            assert_eq!(current_depth(), 1);
+            {
+                let _inner = open("Inner");
+                assert_eq!(current_depth(), 2);
+                assert_eq!(active_titles(), ["Outer", "Inner"]);
+            }
+            assert_eq!(current_depth(), 1);
+            assert_eq!(active_titles(), ["Outer"]);
+        });
+        assert_eq!(current_depth(), 0);
+    }
+
+    #[test]
+    fn depth_unwinds_on_panic() {
+        with_ci_env(&[], || {
+            let result = std::panic::catch_unwind(|| {
+                let _outer = open("Outer");
+                let _inner = open("Inner");
+                panic!("boom");
+            });
+            assert!(result.is_err());
+            assert_eq!(current_depth(), 0);
+        });
+    }
+
+    #[test]
+    fn depth_counts_flattened_github_groups() {
+        let buf = Capture::default();
+        with_ci_env(&[("GITHUB_ACTIONS", "true")], || {
+            let _outer = Group::with_writer("Outer", buf.clone());
+            let _inner = Group::with_writer("Inner", buf.clone());
+            assert_eq!(current_depth(), 2);
+        });
+        assert_eq!(buf.contents().matches("::group::").count(), 1);
+    }
+
+    #[test]
+    fn group_dropped_on_another_thread_leaves_the_stack() {
+        let buf = Capture::default();
+        with_ci_env(&[("GITHUB_ACTIONS", "true")], || {
+            let g = Group::with_writer("Moved", buf.clone());
+            assert_eq!(current_depth(), 1);
+            thread::spawn(move || {
+                assert_eq!(current_depth(), 0);
+                drop(g);
+            })
+            .join()
+            .unwrap();
+            assert_eq!(current_depth(), 0);
+            drop(Group::with_writer("Next", buf.clone()));
+        });
+        assert_eq!(buf.contents().matches("::group::").count(), 2);
+    }
+
+    #[test]
+    fn detected_provider_follows_environment() {
+        with_ci_env(&[("GITHUB_ACTIONS", "true")], || {
+            assert_eq!(detected_provider(), Provider::GitHub);
+        });
+        with_ci_env(&[], || {
+            assert_eq!(detected_provider(), Provider::None);
+            assert!(!detected_provider().is_active());
+        });
+    }
+
+    struct FakeCi;
+
+    impl CiProvider for FakeCi {
+        fn name(&self) -> &'static str {
+            "fake"
+        }
+        fn is_active_env(&self) -> bool {
+            env_is_set("FAKE_CI")
+        }
+        fn open_marker(&self, title: &str) -> String {
+            format!("<<open:{title}>>\n")
+        }
+        fn close_marker(&self, _title: &str) -> String {
+            String::from("<<close>>\n")
+        }
+    }
+
+    #[test]
+    fn registered_provider_wins_detection_and_writes_its_markers() {
+        let buf = Capture::default();
+        with_ci_env(&[("FAKE_CI", "1"), ("GITHUB_ACTIONS", "true")], || {
+            let fake = register_provider(Box::new(FakeCi));
+            assert_eq!(detected_provider(), fake);
+            assert_eq!(fake.name(), "fake");
+            drop(Group::with_writer("Build", buf.clone()));
+        });
+        assert_eq!(buf.contents(), "<<open:Build>>\n<<close>>\n");
+
+        with_ci_env(&[("GITHUB_ACTIONS", "true")], || {
+            assert_eq!(detected_provider(), Provider::GitHub);
+        });
+        with_ci_env(&[("CI_GROUP_PROVIDER", "FAKE")], || {
+            assert!(matches!(detected_provider(), Provider::Custom(_)));
+        });
+    }
+
+    #[test]
+    fn each_provider_renders_its_own_markers() {
+        let banner = (
+            Some("\n========== Build ==========\n"),
+            Some("\n========== end Build ==========\n"),
+        );
+        let cases = [
+            (
+                Provider::GitHub,
+                (Some("\n::group::Build\n"), Some("\n::endgroup::\n")),
+            ),
+            (
+                Provider::Azure,
+                (Some("\n##[group]Build\n"), Some("\n##[endgroup]\n")),
+            ),
+            (
+                Provider::CircleCI,
+                (Some("\n----- Build -----\n"), Some("\n")),
+            ),
+            (Provider::Buildkite, (Some("\n--- Build\n"), None)),
+            (
+                Provider::TeamCity,
+                (
+                    Some("\n##teamcity[blockOpened name='Build']\n"),
+                    Some("\n##teamcity[blockClosed name='Build']\n"),
+                ),
+            ),
+            (Provider::Jenkins, banner),
+            (Provider::Bitbucket, banner),
+            (Provider::Drone, banner),
+            (Provider::Harness, banner),
+            (Provider::AppVeyor, banner),
+            (Provider::Semaphore, banner),
+            (Provider::Generic, banner),
+            (Provider::None, (None, None)),
+        ];
+        for (provider, (open, close)) in cases {
+            let opened = provider.open_marker("Build");
+            assert_eq!(opened.marker(), open, "{provider:?}");
+            assert_eq!(
+                provider.close_marker(opened).as_deref(),
+                close,
+                "{provider:?}"
+            );
+            assert_eq!(close.is_some(), provider.needs_explicit_close());
+        }
+    }
+
+    #[test]
+    fn provider_markers_close_the_group_they_are_given() {
+        let teamcity = Provider::TeamCity;
+        let outer = teamcity.open_marker("Outer");
+        // Looking at a marker leaves nothing behind to close.
+        let _ = teamcity.open_marker("Peek").marker();
+        let inner = teamcity.open_marker("Inner");
+        assert_eq!(
+            teamcity.close_marker(inner).as_deref(),
+            Some("\n##teamcity[blockClosed name='Inner']\n")
+        );
+        assert_eq!(
+            teamcity.close_marker(outer).as_deref(),
+            Some("\n##teamcity[blockClosed name='Outer']\n")
+        );
+    }
+
+    #[test]
+    fn provider_markers_repeat_the_gitlab_section() {
+        let opened = Provider::GitLab.open_marker("Build & Test");
+        let open = opened.marker().unwrap().to_owned();
+        let close = Provider::GitLab.close_marker(opened).unwrap();
+        let start = open.strip_prefix("\n\x1b[0Ksection_start:").unwrap();
+        let (_, start) = start.split_once(':').unwrap();
+        let (name, title) = start.split_once("\r\x1b[0K").unwrap();
+        assert!(name.starts_with("Build___Test_"), "{name}");
+        assert_eq!(title, "Build & Test\n");
+        let end = close.strip_prefix("\n\x1b[0Ksection_end:").unwrap();
+        let (_, end) = end.split_once(':').unwrap();
+        assert_eq!(end, format!("{name}\r\x1b[0K\n"));
+    }
+
+    #[test]
+    fn only_github_lacks_nesting() {
+        assert!(!Provider::GitHub.supports_nesting());
+        assert!(Provider::Azure.supports_nesting());
+        assert!(Provider::GitLab.supports_nesting());
+        assert!(Provider::TeamCity.supports_nesting());
+    }
+
+    #[test]
+    fn only_native_groups_support_folding() {
+        let cases = [
+            (Provider::GitHub, true),
+            (Provider::Azure, true),
+            (Provider::GitLab, true),
+            (Provider::Buildkite, true),
+            (Provider::TeamCity, true),
+            (Provider::CircleCI, false),
+            (Provider::Jenkins, false),
+            (Provider::Bitbucket, false),
+            (Provider::Drone, false),
+            (Provider::Harness, false),
+            (Provider::AppVeyor, false),
+            (Provider::Semaphore, false),
+            (Provider::Generic, false),
+            (Provider::None, false),
+        ];
+        for (provider, folds) in cases {
+            assert_eq!(provider.supports_folding(), folds, "{provider:?}");
+        }
+    }
+
+    #[test]
+    fn supports_folding_follows_detection() {
+        with_ci_env(&[("TF_BUILD", "True")], || assert!(supports_folding()));
+        with_ci_env(&[("JENKINS_URL", "http://ci")], || {
+            assert!(!supports_folding())
+        });
+        with_ci_env(&[], || assert!(!supports_folding()));
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn provider_round_trips_through_serde() {
+        let all = [
+            Provider::GitHub,
+            Provider::Azure,
+            Provider::GitLab,
+            Provider::CircleCI,
+            Provider::Buildkite,
+            Provider::TeamCity,
+            Provider::Jenkins,
+            Provider::Bitbucket,
+            Provider::Drone,
+            Provider::Harness,
+            Provider::AppVeyor,
+            Provider::Semaphore,
+            Provider::Generic,
+            Provider::None,
+        ];
+        for provider in all {
+            let json = serde_json::to_string(&provider).unwrap();
+            assert_eq!(json, format!("\"{}\"", provider.name()));
+            assert_eq!(serde_json::from_str::<Provider>(&json).unwrap(), provider);
+        }
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn provider_deserializes_case_insensitively() {
+        assert_eq!(
+            serde_json::from_str::<Provider>("\"GitHub\"").unwrap(),
+            Provider::GitHub
+        );
+        assert_eq!(
+            serde_json::from_str::<Provider>("\"AZURE\"").unwrap(),
+            Provider::Azure
+        );
+        assert!(serde_json::from_str::<Provider>("\"travis\"").is_err());
+    }
+
+    #[test]
+    fn group_macro_accepts_a_mode() {
+        let buf = Capture::default();
+        with_ci_env(&[], || {
+            set_writer(buf.clone());
+            let forced = group!("Forced", mode = ForceGitHub, { 1 });
+            let title = String::from("Quiet");
+            let disabled = group!(title, mode = Disabled, { 2 });
+            take_writer();
+            assert_eq!((forced, disabled), (1, 2));
+        });
+        assert_eq!(buf.contents(), "\n::group::Forced\n\n::endgroup::\n");
+    }
+
+    #[test]
+    fn group_macro_accepts_plain_and_formatted_titles() {
+        let buf = Capture::default();
+        with_ci_env(&[("GITHUB_ACTIONS", "true")], || {
+            set_writer(buf.clone());
+            let plain = group!("Plain", { 1 });
+            let file = "a.rs";
+            let formatted = group!("Processing {} ({})", file, 2; { 2 });
+            let inline = group!("Inline {file}"; { 3 });
+            take_writer();
+            assert_eq!((plain, formatted, inline), (1, 2, 3));
+        });
+        let out = buf.contents();
+        assert!(out.contains("::group::Plain\n"));
+        assert!(out.contains("::group::Processing a.rs (2)\n"));
+        assert!(out.contains("::group::Inline a.rs\n"));
+        assert_eq!(out.matches("::endgroup::").count(), 3);
+    }
+
+    #[test]
+    fn group_on_error_wraps_failed_output() {
+        let buf = Capture::default();
+        with_ci_env(&[("GITHUB_ACTIONS", "true")], || {
+            set_writer(buf.clone());
+            let result: Result<(), &str> = group_on_error("Fetch", |out| {
+                writeln!(out, "retrying").unwrap();
+                Err("refused")
+            });
+            take_writer();
+            assert_eq!(result, Err("refused"));
+        });
+        assert_eq!(
+            buf.contents(),
+            "\n::group::Fetch\nretrying\n\n::endgroup::\n"
+        );
+    }
+
+    #[test]
+    fn step_annotates_errors_inside_the_group() {
+        let buf = Capture::default();
+        with_ci_env(&[("GITHUB_ACTIONS", "true")], || {
+            set_writer(buf.clone());
+            let ok: Result<u32, &str> = step("Build", || Ok(7));
+            let err: Result<u32, &str> = step("Deploy", || Err("no credentials"));
+            take_writer();
+            assert_eq!(ok, Ok(7));
+            assert_eq!(err, Err("no credentials"));
+        });
+        assert_eq!(
+            buf.contents(),
+            "\n::group::Build\n\n::endgroup::\n\
+             \n::group::Deploy\n::error::no credentials\n\n::endgroup::\n"
+        );
+    }
+
+    #[test]
+    fn group_on_error_prints_or_discards_successful_output() {
+        let buf = Capture::default();
+        with_ci_env(&[("GITHUB_ACTIONS", "true")], || {
+            set_writer(buf.clone());
+            let printed: Result<u32, ()> = group_on_error("Fetch", |out| {
+                writeln!(out, "fetched").unwrap();
+                Ok(1)
+            });
+            let discarded: Result<u32, ()> =
+                group_on_error_with("Fetch", OnSuccess::Discard, |out| {
+                    writeln!(out, "hidden").unwrap();
+                    Ok(2)
+                });
+            take_writer();
+            assert_eq!((printed, discarded), (Ok(1), Ok(2)));
+        });
+        assert_eq!(buf.contents(), "fetched\n");
+    }
+
+    #[test]
+    fn newline_guard_skips_redundant_line_breaks() {
+        let buf = Capture::default();
+        with_ci_env(&[("GITHUB_ACTIONS", "true")], || {
+            set_newline_guard(NewlineGuard::WhenNeeded);
+            set_writer(buf.clone());
+            writeln!(output(), "done").unwrap();
+            let g = open("Build");
+            writeln!(output(), "building").unwrap();
+            drop(g);
+            take_writer();
+            set_newline_guard(NewlineGuard::Always);
+        });
+        assert_eq!(
+            buf.contents(),
+            "done\n::group::Build\nbuilding\n::endgroup::\n"
+        );
+    }
+
+    #[test]
+    fn newline_guard_breaks_unfinished_lines() {
+        let buf = Capture::default();
+        with_ci_env(&[("GITHUB_ACTIONS", "true")], || {
+            set_newline_guard(NewlineGuard::WhenNeeded);
+            set_writer(buf.clone());
+            write!(output(), "partial").unwrap();
+            let g = open("Build");
+            write!(output(), "no newline").unwrap();
+            drop(g);
+            take_writer();
+            set_newline_guard(NewlineGuard::Always);
+        });
+        assert_eq!(
+            buf.contents(),
+            "partial\n::group::Build\nno newline\n::endgroup::\n"
+        );
+    }
+
+    #[test]
+    fn capture_is_stable_and_timestamp_free() {
+        let steps = || {
+            let _outer = open("Build");
+            glog!("compiling");
+            let _inner = open("Link").timed();
+            glog!("linking");
+        };
+        with_ci_env(&[], || {
+            let github = capture(steps);
+            assert_eq!(
+                github,
+                "\n::group::Build\ncompiling\nlinking\n\n::endgroup::\n"
+            );
+            let gitlab = capture_with(Provider::GitLab, steps);
+            assert_eq!(gitlab, capture_with(Provider::GitLab, steps));
+            assert_eq!(
+                gitlab,
+                "\n\x1b[0Ksection_start:0:Build_0\r\x1b[0KBuild\ncompiling\n\
+                 \n\x1b[0Ksection_start:0:Link_1\r\x1b[0KLink\nlinking\n\
+                 \n\x1b[0Ksection_end:0:Link_1\r\x1b[0K\nLink took 0.0s\n\
+                 \n\x1b[0Ksection_end:0:Build_0\r\x1b[0K\n"
+            );
+            assert_eq!(detected_provider(), Provider::None);
+        });
+    }
+
+    #[test]
+    fn batch_holds_stdout_until_it_ends() {
+        let held = || BATCH.with(|batch| batch.borrow().clone());
+        // Taken before the batch ends, so none of it reaches the test's stdout.
+        let take = || BATCH.with(|batch| batch.borrow_mut().as_mut().map(std::mem::take));
+        with_batch(|_| {
+            assert!(Sink::Stdout.write_all(b"one\n"));
+            with_batch(|_| Sink::Stdout.write_all(b"two\n"));
+            assert_eq!(take().as_deref(), Some(&b"one\ntwo\n"[..]));
+            assert_eq!(held().as_deref(), Some(&b""[..]));
+        });
+        assert_eq!(held(), None);
+    }
+
+    #[test]
+    fn leading_newline_can_be_turned_off() {
+        let buf = Capture::default();
+        with_ci_env(&[("GITHUB_ACTIONS", "true")], || {
+            set_writer(buf.clone());
+            writeln!(output(), "done").unwrap();
+            drop(
+                GroupBuilder::new()
+                    .title("Build")
+                    .leading_newline(false)
+                    .open(),
+            );
+            drop(
+                GroupBuilder::new()
+                    .title("Test")
+                    .leading_newline(true)
+                    .open(),
+            );
+            take_writer();
+        });
+        assert_eq!(
+            buf.contents(),
+            "done\n::group::Build\n\n::endgroup::\n\n::group::Test\n\n::endgroup::\n"
+        );
+    }
+
+    #[test]
+    fn crlf_line_ending_keeps_existing_crlf() {
+        with_ci_env(&[], || {
+            set_line_ending(LineEnding::CrLf);
+            let converted = with_line_ending(String::from("\none\r\ntwo\r\n\n"));
+            set_line_ending(LineEnding::Lf);
+            assert_eq!(converted, "\r\none\r\ntwo\r\n\r\n");
+        });
+    }
+
+    #[test]
+    fn crlf_line_ending_applies_to_markers_and_log_lines() {
+        let azure = Capture::default();
+        let gitlab = Capture::default();
+        with_ci_env(&[("TF_BUILD", "true")], || {
+            set_line_ending(LineEnding::CrLf);
+            set_writer(azure.clone());
+            let g = open("Build");
+            glog!("compiling");
+            drop(g);
+            take_writer();
+            set_writer(gitlab.clone());
+            let g = GroupBuilder::new()
+                .title("Test")
+                .provider_override(Provider::GitLab);
+            drop(g.open());
+            take_writer();
+            set_line_ending(LineEnding::Lf);
+        });
+        assert_eq!(
+            azure.contents(),
+            "\r\n##[group]Build\r\ncompiling\r\n\r\n##[endgroup]\r\n"
+        );
+        let gitlab = gitlab.contents();
+        assert!(gitlab.contains("\r\x1b[0KTest\r\n"), "{gitlab:?}");
+        assert!(!gitlab.replace("\r\n", "").contains('\n'), "{gitlab:?}");
+    }
+
+    #[test]
+    fn newline_guard_defaults_to_always() {
+        let buf = Capture::default();
+        with_ci_env(&[("GITHUB_ACTIONS", "true")], || {
+            assert_eq!(newline_guard(), NewlineGuard::Always);
+            set_writer(buf.clone());
+            writeln!(output(), "done").unwrap();
+            drop(open("Build"));
+            take_writer();
+        });
+        assert_eq!(buf.contents(), "done\n\n::group::Build\n\n::endgroup::\n");
+    }
+
+    #[test]
+    fn drone_and_harness_print_banners() {
+        for var in [("DRONE", "true"), ("HARNESS_BUILD_ID", "17")] {
+            let buf = Capture::default();
+            with_ci_env(&[var], || {
+                let _g = Group::with_writer("Deploy", buf.clone());
+            });
+            assert_eq!(
+                buf.contents(),
+                "\n========== Deploy ==========\n\n========== end Deploy ==========\n"
+            );
+        }
+    }
+
+    #[test]
+    fn truncates_long_titles() {
+        with_ci_env(&[("GITHUB_ACTIONS", "true")], || {
+            let long = "€".repeat(2000);
+            let g = Group::with_writer(&long, std::io::sink());
+            // 4093 bytes fit next to the `…`, and the last whole `€` ends at 4092.
+            assert_eq!(g.label().len(), 4092 + "…".len());
+            assert!(g.label().ends_with("€…"));
+            assert!(long.starts_with(g.label().trim_end_matches('…')));
+
+            let exact = "a".repeat(DEFAULT_MAX_TITLE_LEN);
+            assert_eq!(Group::with_writer(&exact, std::io::sink()).label(), exact);
+        });
+    }
+
+    #[test]
+    fn is_real_only_for_groups_that_write_markers() {
+        with_ci_env(&[("GITHUB_ACTIONS", "true")], || {
+            let outer = Group::with_writer("Outer", std::io::sink());
+            assert!(outer.is_real());
+            assert!(!Group::with_writer("Flattened", std::io::sink()).is_real());
+            assert!(!Group::maybe("Skipped", false).is_real());
+        });
+        with_ci_env(&[], || {
+            assert!(!Group::with_writer("Local", std::io::sink()).is_real());
+        });
+    }
+
+    #[test]
+    fn group_reports_its_provider() {
+        with_ci_env(&[("GITHUB_ACTIONS", "true")], || {
+            let outer = Group::with_writer("Outer", std::io::sink());
+            assert_eq!(outer.provider(), Provider::GitHub);
+            let flattened = Group::with_writer("Flattened", std::io::sink());
+            assert_eq!(flattened.provider(), Provider::GitHub);
+            let disabled = open_with("Disabled", Mode::Disabled);
+            assert_eq!(disabled.provider(), Provider::None);
+        });
+    }
+
+    #[test]
+    fn recent_groups_keeps_the_latest_records_in_order() {
+        let mine = || -> Vec<(String, usize, bool)> {
+            let thread = thread::current().id();
+            recent_groups(GROUP_HISTORY_LEN)
+                .into_iter()
+                .filter(|record| record.thread == thread)
+                .map(|record| (record.title, record.depth, record.closed.is_some()))
+                .collect()
+        };
+        with_ci_env(&[], || {
+            let build = open("Build");
+            drop(open("Fetch"));
+            let compile = open("Compile");
+            let link = open("link");
+            assert_eq!(
+                mine(),
+                [
+                    ("Build".to_owned(), 1, false),
+                    ("Fetch".to_owned(), 2, true),
+                    ("Compile".to_owned(), 2, false),
+                    ("link".to_owned(), 3, false),
+                ]
+            );
+            drop((link, compile, build));
+            assert!(mine().iter().all(|&(_, _, closed)| closed));
+            assert_eq!(recent_groups(1).len(), 1);
+
+            for i in 0..GROUP_HISTORY_LEN {
+                drop(open(format!("Step {i}")));
+            }
+            let last = mine().pop().unwrap();
+            assert_eq!(last.0, format!("Step {}", GROUP_HISTORY_LEN - 1));
+            assert_eq!(recent_groups(usize::MAX).len(), GROUP_HISTORY_LEN);
+        });
+    }
+
+    #[test]
+    fn static_titles_are_kept_or_normalized() {
+        with_ci_env(&[], || {
+            let clean = open_static("Build");
+            assert_eq!(clean.label(), "Build");
+            assert!(matches!(clean.title, Cow::Borrowed("Build")));
+            assert_eq!(open_static("Build\r\nall").label(), "Build all");
+            let _prefix = with_title_prefix("[api] ");
+            assert_eq!(open_static("Build").label(), "[api] Build");
+        });
+    }
+
+    #[test]
+    fn set_title_updates_the_footer_banner() {
+        let buf = Capture::default();
+        with_ci_env(&[("JENKINS_URL", "x")], || {
+            let mut g = Group::with_writer("Processing...", buf.clone());
+            g.set_title("Processed\n42 files");
+            assert_eq!(active_titles(), ["Processed 42 files"]);
+        });
+        assert_eq!(
+            buf.contents(),
+            "\n========== Processing... ==========\n\n========== end Processed 42 files ==========\n"
+        );
+    }
+
+    #[test]
+    fn set_title_keeps_end_markers_paired() {
+        let buf = Capture::default();
+        with_ci_env(&[("TEAMCITY_VERSION", "1")], || {
+            Group::with_writer("Before", buf.clone()).set_title("After");
+        });
+        assert_eq!(
+            buf.contents(),
+            "\n##teamcity[blockOpened name='Before']\n\n##teamcity[blockClosed name='Before']\n"
+        );
+
+        let buf = Capture::default();
+        with_ci_env(&[("GITHUB_ACTIONS", "true")], || {
+            let mut g = Group::with_writer("Before", buf.clone()).timed();
+            g.set_title("After");
+        });
+        let out = buf.contents();
+        assert!(out.starts_with("\n::group::Before\n\n::endgroup::\nAfter took "));
+    }
+
+    #[test]
+    fn colors_banners_when_forced() {
+        let banners = |vars: &[(&str, &str)]| {
+            let buf = Capture::default();
+            let mut env = vec![("JENKINS_URL", "x")];
+            env.extend_from_slice(vars);
+            with_ci_env(&env, || {
+                set_writer(buf.clone());
+                drop(open("Build"));
+                take_writer();
+            });
+            buf.contents()
+        };
+        // Not a terminal.
+        assert!(!banners(&[]).contains('\x1b'));
+        assert_eq!(
+            banners(&[("FORCE_COLOR", "1")]),
+            "\n\x1b[1m========== Build ==========\x1b[0m\n\n\x1b[2m========== end Build ==========\x1b[0m\n"
+        );
+        assert!(banners(&[("CLICOLOR_FORCE", "1")]).contains("\x1b[1m"));
+        assert!(!banners(&[("FORCE_COLOR", "0")]).contains('\x1b'));
+        assert!(!banners(&[("FORCE_COLOR", "1"), ("NO_COLOR", "1")]).contains('\x1b'));
+    }
+
+    #[test]
+    fn native_groups_are_never_colored() {
+        let buf = Capture::default();
+        with_ci_env(&[("GITHUB_ACTIONS", "true"), ("FORCE_COLOR", "1")], || {
+            drop(Group::with_writer("Build", buf.clone()));
+        });
+        assert_eq!(buf.contents(), "\n::group::Build\n\n::endgroup::\n");
+    }
+
+    #[test]
+    fn appveyor_prints_banners() {
+        let buf = Capture::default();
+        with_ci_env(&[("APPVEYOR", "True")], || {
+            drop(Group::with_writer("Test", buf.clone()));
+        });
+        assert_eq!(
+            buf.contents(),
+            "\n========== Test ==========\n\n========== end Test ==========\n"
+        );
+    }
+
+    #[test]
+    fn detection_precedence_is_documented_order() {
+        let cases: &[(&[(&str, &str)], Provider)] = &[
+            (
+                &[("GITHUB_ACTIONS", "true"), ("TF_BUILD", "true")],
+                Provider::GitHub,
+            ),
+            (
+                &[("GITHUB_ACTIONS", "true"), ("GITLAB_CI", "true")],
+                Provider::GitHub,
+            ),
+            (
+                &[("TF_BUILD", "true"), ("GITLAB_CI", "true")],
+                Provider::Azure,
+            ),
+            (
+                &[
+                    ("GITHUB_ACTIONS", "true"),
+                    (
+                        "SYSTEM_TEAMFOUNDATIONCOLLECTIONURI",
+                        "https://dev.azure.com/acme/",
+                    ),
+                ],
+                Provider::GitHub,
+            ),
+            (
+                &[("GITLAB_CI", "true"), ("JENKINS_URL", "x")],
+                Provider::GitLab,
+            ),
+            (
+                &[("BUILDKITE", "true"), ("TEAMCITY_VERSION", "1")],
+                Provider::Buildkite,
+            ),
+            (
+                &[("TEAMCITY_VERSION", "1"), ("JENKINS_URL", "x")],
+                Provider::TeamCity,
+            ),
+            (
+                &[("JENKINS_URL", "x"), ("BITBUCKET_BUILD_NUMBER", "1")],
+                Provider::Jenkins,
+            ),
+        ];
+        for &(vars, expected) in cases {
+            with_ci_env(vars, || {
+                assert_eq!(Provider::detect(), expected, "{vars:?}");
+            });
+        }
+    }
+
+    #[test]
+    fn detection_order_lists_each_provider_once() {
+        for (i, (provider, _)) in DETECTION_ORDER.iter().enumerate() {
+            assert!(provider.is_active());
+            assert!(!DETECTION_ORDER[i + 1..].iter().any(|(p, _)| p == provider));
+        }
+    }
+
+    #[test]
+    fn titles_accept_any_string_type() {
+        use std::borrow::Cow;
+
+        let buf = Capture::default();
+        with_ci_env(&[("GITHUB_ACTIONS", "true")], || {
+            set_writer(buf.clone());
+            drop(open("Borrowed"));
+            drop(open(String::from("Owned")));
+            drop(open(Cow::Borrowed("Cow")));
+            drop(GroupBuilder::new().title(String::from("Builder")).open());
+            let title = String::from("Macro");
+            group!(title, {});
+            #[cfg(feature = "async")]
+            block_on(grouped(format!("Async {}", 1), async {}));
+            take_writer();
+        });
+        let out = buf.contents();
+        #[cfg(feature = "async")]
+        assert!(out.contains("::group::Async 1\n"));
+        for title in ["Borrowed", "Owned", "Cow", "Builder", "Macro"] {
+            assert!(out.contains(&format!("::group::{title}\n")), "{title}");
+        }
+    }
+
+    #[test]
+    fn output_stream_round_trips() {
+        with_ci_env(&[], || {
+            let custom = OutputStream::Custom(Arc::new(Mutex::new(Capture::default())));
+            for stream in [OutputStream::Stderr, custom, OutputStream::Stdout] {
+                set_output_stream(stream.clone());
+                assert_eq!(output_stream(), stream);
+            }
+        });
+    }
+
+    #[test]
+    fn custom_stream_takes_both_markers() {
+        let buf = Capture::default();
+        let other = Capture::default();
+        with_ci_env(&[("GITHUB_ACTIONS", "true")], || {
+            let stream = OutputStream::Custom(Arc::new(Mutex::new(buf.clone())));
+            set_output_stream(OutputStream::Custom(Arc::new(Mutex::new(other.clone()))));
+            let g = open_with("Shared", stream.clone());
+            set_output_stream(OutputStream::Stdout);
+            drop(g);
+            drop(open_with("Again", stream));
+        });
+        assert_eq!(
+            buf.contents(),
+            "\n::group::Shared\n\n::endgroup::\n\n::group::Again\n\n::endgroup::\n"
+        );
+        assert_eq!(other.contents(), "");
+    }
+
+    #[test]
+    fn custom_stream_is_deferred_like_the_others() {
+        let buf = Capture::default();
+        with_ci_env(&[("GITHUB_ACTIONS", "true")], || {
+            set_flush_mode(FlushMode::Deferred);
+            let stream = OutputStream::Custom(Arc::new(Mutex::new(buf.clone())));
+            let g = open_with("Held", stream);
+            assert_eq!(buf.contents(), "");
+            drop(g);
+            set_flush_mode(FlushMode::Immediate);
+        });
+        assert_eq!(buf.contents(), "\n::group::Held\n\n::endgroup::\n");
+    }
+
+    #[test]
+    fn maybe_only_groups_when_enabled() {
+        let buf = Capture::default();
+        with_ci_env(&[("GITHUB_ACTIONS", "true")], || {
+            set_writer(buf.clone());
+            drop(Group::maybe("Skipped", false));
+            let skipped = buf.contents();
+            drop(Group::maybe("Shown", true));
+            take_writer();
+            assert!(skipped.is_empty());
+        });
+        assert_eq!(buf.contents(), "\n::group::Shown\n\n::endgroup::\n");
+    }
+
+    /// Counts the writes and flushes made on the thread that created it, so tests running
+    /// alongside can't change the numbers.
+    #[derive(Clone)]
+    struct FlushCounter {
+        thread: ThreadId,
+        writes: Arc<AtomicUsize>,
+        flushes: Arc<AtomicUsize>,
+        bytes: Capture,
+    }
+
+    impl FlushCounter {
+        fn new() -> Self {
+            FlushCounter {
+                thread: thread::current().id(),
+                writes: Arc::default(),
+                flushes: Arc::default(),
+                bytes: Capture::default(),
+            }
+        }
+
+        fn counts(&self) -> (usize, usize) {
+            let writes = self.writes.load(Ordering::Relaxed);
+            (writes, self.flushes.load(Ordering::Relaxed))
+        }
+    }
+
+    impl Write for FlushCounter {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            if thread::current().id() == self.thread {
+                self.writes.fetch_add(1, Ordering::Relaxed);
+                self.bytes.write_all(buf)?;
+            }
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            if thread::current().id() == self.thread {
+                self.flushes.fetch_add(1, Ordering::Relaxed);
+            }
+            Ok(())
+        }
+    }
+
+    /// Writes and flushes for an outer group around ten inner ones, with their output.
+    fn io_for_nested_groups(mode: FlushMode) -> ((usize, usize), String) {
+        let counter = FlushCounter::new();
+        with_ci_env(&[("TF_BUILD", "true")], || {
+            set_flush_mode(mode);
+            set_writer(counter.clone());
+            let outer = open("Outer");
+            for i in 0..10 {
+                let mut step = open(format!("Step {i}"));
+                step.write_all(format!("step {i}\n").as_bytes()).unwrap();
+            }
+            drop(outer);
+            take_writer();
+            set_flush_mode(FlushMode::Immediate);
+        });
+        (counter.counts(), counter.bytes.contents())
+    }
+
+    #[test]
+    fn deferred_flush_waits_for_outermost_group() {
+        let (immediate, expected) = io_for_nested_groups(FlushMode::Immediate);
+        assert_eq!(immediate, (32, 32));
+        let (deferred, out) = io_for_nested_groups(FlushMode::Deferred);
+        // Everything up to the outer close in one write, the close itself, one flush.
+        assert_eq!(deferred, (2, 1));
+        assert_eq!(out, expected);
+    }
+
+    #[test]
+    fn deferred_flush_is_per_thread() {
+        let counter = FlushCounter::new();
+        with_ci_env(&[("TF_BUILD", "true")], || {
+            set_flush_mode(FlushMode::Deferred);
+            set_writer(counter.clone());
+            let (opened, close) = (mpsc::channel(), mpsc::channel::<()>());
+            let other = thread::spawn(move || {
+                let _g = open("Elsewhere");
+                opened.0.send(()).unwrap();
+                close.1.recv().unwrap();
+            });
+            opened.1.recv().unwrap();
+            drop(open("Here"));
+            // Written out although the other thread still has a group open.
+            assert_eq!(counter.counts(), (2, 1));
+            close.0.send(()).unwrap();
+            other.join().unwrap();
+            take_writer();
+            set_flush_mode(FlushMode::Immediate);
+        });
+        assert_eq!(
+            counter.bytes.contents(),
+            "\n##[group]Here\n\n##[endgroup]\n"
+        );
+    }
+
+    #[test]
+    fn deferred_flush_buffers_own_writer_until_close() {
+        let counter = FlushCounter::new();
+        with_ci_env(&[("TF_BUILD", "true")], || {
+            set_flush_mode(FlushMode::Deferred);
+            let mut own = Group::with_writer("Own", counter.clone());
+            own.write_all(b"inside\n").unwrap();
+            assert_eq!(counter.counts(), (0, 0));
+            drop(own);
+            set_flush_mode(FlushMode::Immediate);
+        });
+        assert_eq!(counter.counts(), (1, 1));
+        assert_eq!(
+            counter.bytes.contents(),
+            "\n##[group]Own\ninside\n\n##[endgroup]\n"
+        );
+    }
+
+    #[test]
+    fn adversarial_titles_keep_markers_balanced() {
+        let titles = [
+            "##[endgroup]",
+            "x\n##[endgroup]\n##[group]y",
+            "::endgroup::",
+            "a\n::endgroup::\n::group::b",
+            "50%0A::endgroup::",
+        ];
+        for (var, open_marker, close_marker) in [
+            ("GITHUB_ACTIONS", "::group::", "::endgroup::"),
+            ("TF_BUILD", "##[group]", "##[endgroup]"),
+        ] {
+            for title in titles {
+                let buf = Capture::default();
+                with_ci_env(&[(var, "true")], || {
+                    let _g = Group::with_writer(title, buf.clone()).timed();
+                });
+                let out = buf.contents();
+                let opens = out.lines().filter(|l| l.starts_with(open_marker)).count();
+                let closes = out.lines().filter(|l| l.starts_with(close_marker)).count();
+                assert_eq!((opens, closes), (1, 1), "{title:?}: {out:?}");
+                let commands = out
+                    .lines()
+                    .filter(|l| l.starts_with("::") || l.starts_with("##"))
+                    .count();
+                let extra = usize::from(var == "TF_BUILD");
+                assert_eq!(commands, 2 + extra, "{title:?}: {out:?}");
+            }
+        }
+    }
+
+    #[test]
+    fn scope_returns_value_and_closes_on_panic() {
+        let buf = Capture::default();
+        with_ci_env(&[("GITHUB_ACTIONS", "true")], || {
+            set_writer(buf.clone());
+            assert_eq!(scope("Value", || 7), 7);
+            let result = std::panic::catch_unwind(|| scope("Panics", || panic!("boom")));
+            take_writer();
+            assert!(result.is_err());
+        });
+        assert_eq!(
+            buf.contents(),
+            "\n::group::Value\n\n::endgroup::\n\n::group::Panics\n\n::endgroup::\n"
+        );
+    }
+}
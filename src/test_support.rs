@@ -0,0 +1,76 @@
+//! Helpers shared by the unit tests.
+
+use std::io::Write;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+
+use crate::reset_provider_cache;
+
+/// A cloneable in-memory writer for asserting on emitted markers.
+#[derive(Clone, Default)]
+pub(crate) struct Capture(Arc<Mutex<Vec<u8>>>);
+
+impl Capture {
+    pub(crate) fn contents(&self) -> String {
+        String::from_utf8_lossy(&self.0.lock().unwrap()).into_owned()
+    }
+}
+
+impl Write for Capture {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.0.lock().unwrap().extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Every env var that influences detection or marker formatting.
+const CI_VARS: &[&str] = &[
+    "CI_GROUP_PROVIDER",
+    "CI_GROUP_DISABLE",
+    "GITHUB_ACTIONS",
+    "ACT",
+    "TF_BUILD",
+    "SYSTEM_TEAMFOUNDATIONCOLLECTIONURI",
+    "GITLAB_CI",
+    "CIRCLECI",
+    "BUILDKITE",
+    "TEAMCITY_VERSION",
+    "JENKINS_URL",
+    "BITBUCKET_BUILD_NUMBER",
+    "DRONE",
+    "HARNESS_BUILD_ID",
+    "APPVEYOR",
+    "SEMAPHORE",
+    "CI",
+    "NO_COLOR",
+    "FORCE_COLOR",
+    "CLICOLOR",
+    "CLICOLOR_FORCE",
+];
+
+/// Runs `f` with only the given CI variables set, so the host CI can't leak into the test.
+pub(crate) fn with_ci_env<R>(vars: &[(&str, &str)], f: impl FnOnce() -> R) -> R {
+    let mut env: Vec<(&str, Option<&str>)> = CI_VARS
+        .iter()
+        .filter(|name| !vars.iter().any(|(k, _)| k == *name))
+        .map(|&name| (name, None))
+        .collect();
+    env.extend(vars.iter().map(|&(k, v)| (k, Some(v))));
+    temp_env::with_vars(env, || {
+        reset_provider_cache();
+        let result = f();
+        reset_provider_cache();
+        result
+    })
+}
+
+/// A fresh path in the temp dir, unique to this process and `name`. The file is not created.
+pub(crate) fn temp_path(name: &str) -> PathBuf {
+    let path = std::env::temp_dir().join(format!("ci_group-{}-{name}", std::process::id()));
+    let _ = std::fs::remove_file(&path);
+    path
+}
@@ -0,0 +1,53 @@
+#![cfg(feature = "std")]
+
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::cell::Cell;
+
+/// Counts allocations made on threads that opted in.
+struct Counting;
+
+thread_local! {
+    static COUNTING: Cell<bool> = const { Cell::new(false) };
+    static ALLOCATIONS: Cell<usize> = const { Cell::new(0) };
+}
+
+unsafe impl GlobalAlloc for Counting {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        if COUNTING.with(Cell::get) {
+            ALLOCATIONS.with(|n| n.set(n.get() + 1));
+        }
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+}
+
+#[global_allocator]
+static ALLOCATOR: Counting = Counting;
+
+fn allocations(f: impl FnOnce()) -> usize {
+    ALLOCATIONS.with(|n| n.set(0));
+    COUNTING.with(|c| c.set(true));
+    f();
+    COUNTING.with(|c| c.set(false));
+    ALLOCATIONS.with(Cell::get)
+}
+
+#[test]
+fn static_titles_skip_the_title_copies() {
+    temp_env::with_var("CI_GROUP_PROVIDER", Some("github"), || {
+        ci_group::set_writer(std::io::sink());
+        // Detection, and the registries reaching their working size.
+        for _ in 0..100 {
+            drop(ci_group::open("Warm-up"));
+        }
+
+        let dynamic = allocations(|| drop(ci_group::open("Build")));
+        let literal = allocations(|| drop(ci_group::open_static("Build")));
+        ci_group::take_writer();
+        // `open` copies and normalizes the title; how many allocations that takes is up to std.
+        assert!(literal < dynamic, "{literal} vs {dynamic}");
+    });
+}
@@ -0,0 +1,42 @@
+use std::fmt::Debug;
+use std::future::Future;
+use std::num::ParseIntError;
+use std::pin::pin;
+use std::task::{Context, Poll, Waker};
+
+use ci_group::ci_group;
+
+#[ci_group("Parse")]
+fn parse(input: &str) -> Result<u32, ParseIntError> {
+    let n = input.parse()?;
+    if n == 0 {
+        return Ok(1);
+    }
+    println!("parsed {n}");
+    Ok(n)
+}
+
+#[ci_group]
+fn show<T: Debug>(value: T) {
+    println!("showing {value:?}");
+}
+
+#[ci_group("Fetch")]
+async fn fetch() -> u32 {
+    println!("fetching");
+    7
+}
+
+fn main() {
+    assert_eq!(parse("42"), Ok(42));
+    assert!(parse("x").is_err());
+    assert_eq!(parse("0"), Ok(1));
+    show([1, 2]);
+
+    let mut future = pin!(fetch());
+    println!("not polled yet");
+    let polled = future
+        .as_mut()
+        .poll(&mut Context::from_waker(Waker::noop()));
+    assert_eq!(polled, Poll::Ready(7));
+}
@@ -1,5 +1,4 @@
 fn main() {
-
     let _g = ci_group::open("Test Group");
     println!("inside group");
-}
\ No newline at end of file
+}
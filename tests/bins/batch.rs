@@ -0,0 +1,12 @@
+use std::process::Command;
+
+fn main() {
+    ci_group::with_batch(|batch| {
+        for name in ["core", "cli"] {
+            let _g = batch.open(name);
+            batch.log(format_args!("checking {name}"));
+        }
+        // The batched markers go out before the child writes.
+        ci_group::run_in_group("Echo", Command::new("echo").arg("hello")).unwrap();
+    });
+}
@@ -0,0 +1,7 @@
+fn main() {
+    for i in 0..10_000 {
+        let _g = ci_group::open(format!("Group {i}"));
+        ci_group::glog!("line {i}");
+    }
+    eprintln!("done");
+}
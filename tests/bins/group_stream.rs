@@ -0,0 +1,11 @@
+use ci_group::{GroupBuilder, OutputStream};
+
+fn main() {
+    let _g = GroupBuilder::new()
+        .title("Diagnostics")
+        .stream(OutputStream::Stderr)
+        .open();
+    // A later global change doesn't move the end marker.
+    ci_group::set_output_stream(OutputStream::Stdout);
+    eprintln!("checking");
+}
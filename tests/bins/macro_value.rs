@@ -0,0 +1,7 @@
+fn main() {
+    let n = ci_group::group!("Count", {
+        println!("counting");
+        42
+    });
+    println!("result={n}");
+}
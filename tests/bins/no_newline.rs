@@ -3,4 +3,3 @@ fn main() {
     let _g = ci_group::open("After Print");
     println!("inside");
 }
-
@@ -0,0 +1,6 @@
+fn main() {
+    ci_group::install_panic_group();
+    ci_group::install_panic_group();
+    let _g = ci_group::open("Panic Group");
+    panic!("kaboom");
+}
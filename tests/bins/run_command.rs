@@ -0,0 +1,6 @@
+use std::process::Command;
+
+fn main() {
+    let status = ci_group::run_in_group("Echo", Command::new("echo").arg("hello")).unwrap();
+    assert!(status.success());
+}
@@ -0,0 +1,5 @@
+fn main() {
+    ci_group::set_output_stream(ci_group::OutputStream::Stderr);
+    let _g = ci_group::open("Logged");
+    eprintln!("INFO inside");
+}
@@ -0,0 +1,29 @@
+use tracing_subscriber::layer::SubscriberExt;
+
+fn main() {
+    let layer = ci_group::CiGroupLayer::new()
+        .with_target("ci")
+        .with_field("title");
+    tracing::subscriber::set_global_default(tracing_subscriber::registry().with(layer)).unwrap();
+
+    let build = tracing::info_span!(target: "ci", "build", title = "Build all");
+    {
+        let _e = build.enter();
+        println!("inside build");
+        let inner = tracing::info_span!(target: "ci", "inner");
+        let _i = inner.enter();
+        println!("inside inner");
+    }
+    {
+        let _e = build.enter();
+        println!("reentered");
+    }
+
+    let other = tracing::info_span!(target: "other", "skipped");
+    other.in_scope(|| println!("outside"));
+
+    // Entered but never exited: the group still closes when the span closes.
+    let dropped = tracing::info_span!(target: "ci", "dropped");
+    std::mem::forget(dropped.enter());
+    drop(dropped);
+}
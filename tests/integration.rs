@@ -1,4 +1,6 @@
-use std::process::Command;
+#![cfg(feature = "std")]
+
+use std::process::{Command, Stdio};
 
 #[test]
 fn prints_group_markers() {
@@ -6,7 +8,7 @@ fn prints_group_markers() {
         .env("GITHUB_ACTIONS", "true")
         .output()
         .unwrap();
-    
+
     let stdout = String::from_utf8_lossy(&output.stdout);
     assert!(stdout.contains("::group::Test Group"));
     assert!(stdout.contains("inside group"));
@@ -19,19 +21,60 @@ fn closes_group_on_panic() {
         .env("GITHUB_ACTIONS", "true")
         .output()
         .unwrap();
-    
+
     let stdout = String::from_utf8_lossy(&output.stdout);
     assert!(stdout.contains("::group::Panic Group"));
     assert!(stdout.contains("::endgroup::"));
 }
 
+#[test]
+fn survives_closed_stdout() {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_broken_pipe"))
+        .env("GITHUB_ACTIONS", "true")
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    // More output than the pipe buffer holds, so the child writes after the read end is gone.
+    drop(child.stdout.take());
+
+    let output = child.wait_with_output().unwrap();
+    assert!(output.status.success());
+    assert_eq!(String::from_utf8_lossy(&output.stderr), "done\n");
+}
+
+#[test]
+fn panic_hook_reports_an_error_annotation() {
+    let output = Command::new(env!("CARGO_BIN_EXE_panic_hook"))
+        .env("GITHUB_ACTIONS", "true")
+        .output()
+        .unwrap();
+
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let errors: Vec<&str> = stdout
+        .lines()
+        .filter(|l| l.starts_with("::error "))
+        .collect();
+    assert_eq!(errors.len(), 1, "{stdout}");
+    assert!(errors[0].contains("file=tests/bins/panic_hook.rs,line=5,"));
+    assert!(errors[0].ends_with("title=panic::kaboom"));
+
+    let open = stdout.find("::group::Panic Group").unwrap();
+    let error = stdout.find("::error ").unwrap();
+    let close = stdout.find("::endgroup::").unwrap();
+    assert!(open < error && error < close);
+
+    // The default hook still runs.
+    assert!(String::from_utf8_lossy(&output.stderr).contains("kaboom"));
+}
+
 #[test]
 fn silent_outside_github() {
     let output = Command::new(env!("CARGO_BIN_EXE_basic"))
         .env_remove("GITHUB_ACTIONS")
         .output()
         .unwrap();
-    
+
     let stdout = String::from_utf8_lossy(&output.stdout);
     assert!(!stdout.contains("::group::"));
     assert!(!stdout.contains("::endgroup::"));
@@ -44,8 +87,143 @@ fn handles_missing_newline() {
         .env("GITHUB_ACTIONS", "true")
         .output()
         .unwrap();
-    
+
     let stdout = String::from_utf8_lossy(&output.stdout);
     assert!(stdout.contains("\n::group::After Print"));
     assert!(stdout.contains("\n::endgroup::"));
-}
\ No newline at end of file
+}
+
+#[test]
+fn macro_returns_block_value_after_closing() {
+    let output = Command::new(env!("CARGO_BIN_EXE_macro_value"))
+        .env("GITHUB_ACTIONS", "true")
+        .output()
+        .unwrap();
+
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let open = stdout.find("::group::Count").unwrap();
+    let body = stdout.find("counting").unwrap();
+    let close = stdout.find("::endgroup::").unwrap();
+    let result = stdout.find("result=42").unwrap();
+    assert!(open < body && body < close && close < result);
+}
+
+#[test]
+fn writes_markers_to_selected_stream() {
+    let output = Command::new(env!("CARGO_BIN_EXE_stderr"))
+        .env("GITHUB_ACTIONS", "true")
+        .output()
+        .unwrap();
+
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stdout.is_empty());
+    let open = stderr.find("::group::Logged").unwrap();
+    let body = stderr.find("INFO inside").unwrap();
+    let close = stderr.find("::endgroup::").unwrap();
+    assert!(open < body && body < close);
+}
+
+#[test]
+fn group_keeps_its_stream() {
+    let output = Command::new(env!("CARGO_BIN_EXE_group_stream"))
+        .env("GITHUB_ACTIONS", "true")
+        .output()
+        .unwrap();
+
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stdout.is_empty());
+    let open = stderr.find("::group::Diagnostics").unwrap();
+    let body = stderr.find("checking").unwrap();
+    let close = stderr.find("::endgroup::").unwrap();
+    assert!(open < body && body < close);
+}
+
+#[cfg(feature = "tracing")]
+#[test]
+fn tracing_spans_become_groups() {
+    let output = Command::new(env!("CARGO_BIN_EXE_tracing_spans"))
+        .env("GITHUB_ACTIONS", "true")
+        .output()
+        .unwrap();
+
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert_eq!(stdout.matches("::group::Build all").count(), 2);
+    assert_eq!(stdout.matches("::endgroup::").count(), 3);
+    assert!(!stdout.contains("::group::inner"));
+    assert!(!stdout.contains("::group::skipped"));
+    assert!(stdout.contains("::group::dropped"));
+
+    let reentered = stdout.find("reentered").unwrap();
+    let second_open = stdout.rfind("::group::Build all").unwrap();
+    assert!(second_open < reentered);
+}
+
+#[cfg(feature = "macros")]
+#[test]
+fn attribute_wraps_function_bodies() {
+    let output = Command::new(env!("CARGO_BIN_EXE_attribute"))
+        .env("GITHUB_ACTIONS", "true")
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let lines: Vec<&str> = stdout.lines().filter(|l| !l.is_empty()).collect();
+    assert_eq!(
+        lines,
+        [
+            "::group::Parse",
+            "parsed 42",
+            "::endgroup::",
+            "::group::Parse",
+            "::endgroup::",
+            "::group::Parse",
+            "::endgroup::",
+            "::group::show",
+            "showing [1, 2]",
+            "::endgroup::",
+            "not polled yet",
+            "::group::Fetch",
+            "fetching",
+            "::endgroup::",
+        ]
+    );
+}
+
+#[test]
+fn runs_command_inside_group() {
+    let output = Command::new(env!("CARGO_BIN_EXE_run_command"))
+        .env("GITHUB_ACTIONS", "true")
+        .output()
+        .unwrap();
+
+    assert!(output.status.success());
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let open = stdout.find("::group::Echo\n").expect("group opened");
+    let echoed = stdout.find("$ echo hello\n").expect("command line echoed");
+    let hello = stdout.find("\nhello\n").expect("child output captured");
+    let close = stdout.find("::endgroup::").expect("group closed");
+    assert!(
+        open < echoed && echoed < hello && hello < close,
+        "{stdout:?}"
+    );
+}
+
+#[test]
+fn batch_writes_markers_in_order() {
+    let output = Command::new(env!("CARGO_BIN_EXE_batch"))
+        .env("GITHUB_ACTIONS", "true")
+        .output()
+        .unwrap();
+
+    assert!(output.status.success());
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert_eq!(
+        stdout,
+        "\n::group::core\nchecking core\n\n::endgroup::\n\
+         \n::group::cli\nchecking cli\n\n::endgroup::\n\
+         \n::group::Echo\n$ echo hello\nhello\n\n::endgroup::\n"
+    );
+}
@@ -0,0 +1,21 @@
+#![cfg(feature = "std")]
+
+use ci_group::prelude::*;
+
+#[test]
+fn prelude_covers_the_common_items() {
+    let value = group!("Build", mode = Disabled, {
+        glog!("compiling");
+        7
+    });
+    assert_eq!(value, 7);
+
+    let builder: GroupBuilder = GroupBuilder::new().title("Test").mode(Mode::Disabled);
+    let group: Group = builder.open();
+    assert!(!group.is_real());
+    drop(open_with("Lint", Mode::Disabled));
+
+    let _: fn() -> Provider = detected_provider;
+    let _: fn() -> Group = || open("Docs");
+    let _: [fn(&str); 3] = [error, warning, notice];
+}
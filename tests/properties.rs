@@ -0,0 +1,138 @@
+#![cfg(feature = "std")]
+
+//! Property tests for the escaping in `markers` and `Annotation::render`.
+
+use ci_group::markers::{close_marker, open_marker, sanitize_title, timing_line};
+use ci_group::{Annotation, Provider};
+use proptest::prelude::*;
+use std::time::Duration;
+
+const PROVIDERS: &[Provider] = &[
+    Provider::GitHub,
+    Provider::Azure,
+    Provider::GitLab,
+    Provider::CircleCI,
+    Provider::Buildkite,
+    Provider::TeamCity,
+    Provider::Jenkins,
+    Provider::Bitbucket,
+    Provider::Drone,
+    Provider::Harness,
+    Provider::AppVeyor,
+    Provider::Semaphore,
+    Provider::Generic,
+    Provider::None,
+];
+
+/// Arbitrary text, weighted towards the characters the escaping has to deal with.
+fn text() -> impl Strategy<Value = String> {
+    prop_oneof![
+        "[a-z #:%,'|\\[\\]\r\n]{0,40}",
+        "(##|::|##vso|teamcity|\\[|\\]|%0A|group|endgroup|\r|\n|x){0,12}",
+        any::<String>(),
+    ]
+}
+
+fn provider() -> impl Strategy<Value = Provider> {
+    proptest::sample::select(PROVIDERS)
+}
+
+/// Strips the line breaks a marker starts and ends with.
+fn payload(marker: &str) -> &str {
+    marker.trim_matches('\n')
+}
+
+proptest! {
+    #[test]
+    fn markers_stay_on_one_line(provider in provider(), title in text()) {
+        let title = sanitize_title(&title);
+        for marker in [open_marker(provider, &title), close_marker(provider, &title)] {
+            let payload = payload(&marker);
+            prop_assert!(!payload.contains('\n'), "{marker:?}");
+            if provider != Provider::GitLab {
+                // GitLab's own marker contains a `\r`; everyone else's must not.
+                prop_assert!(!payload.contains('\r'), "{marker:?}");
+            }
+        }
+    }
+
+    #[test]
+    fn github_titles_cannot_end_the_command(title in text()) {
+        let marker = open_marker(Provider::GitHub, &title);
+        let payload = payload(&marker).strip_prefix("::group::").unwrap();
+        prop_assert!(!payload.contains(['\r', '\n']));
+        prop_assert_eq!(close_marker(Provider::GitHub, &title), "\n::endgroup::\n");
+    }
+
+    #[test]
+    fn azure_titles_cannot_start_commands(title in text()) {
+        let title = sanitize_title(&title);
+        let marker = open_marker(Provider::Azure, &title);
+        let payload = payload(&marker).strip_prefix("##[group]").unwrap();
+        prop_assert!(!payload.contains("##[") && !payload.contains("##vso["), "{marker:?}");
+    }
+
+    #[test]
+    fn teamcity_titles_stay_quoted(title in text()) {
+        let marker = open_marker(Provider::TeamCity, &title);
+        let value = payload(&marker)
+            .strip_prefix("##teamcity[blockOpened name='")
+            .and_then(|rest| rest.strip_suffix("']"))
+            .unwrap();
+        let unescaped: String = value.split("||").collect::<Vec<_>>().join("");
+        let unescaped = unescaped.replace("|'", "").replace("|[", "").replace("|]", "");
+        prop_assert!(!unescaped.contains(['\'', '[', ']', '\n', '\r']), "{marker:?}");
+    }
+
+    #[test]
+    fn timing_lines_cannot_start_commands(provider in provider(), title in text()) {
+        let title = sanitize_title(&title);
+        let line = timing_line(provider, &title, Duration::from_millis(1500));
+        prop_assert_eq!(line.matches('\n').count(), 1);
+        match provider {
+            Provider::Azure => {
+                let text = line.strip_prefix("##[command]").unwrap();
+                prop_assert!(!text.contains("##[") && !text.contains("##vso["), "{line:?}");
+            }
+            Provider::GitHub => {
+                prop_assert!(!line.starts_with("::") && !line.contains("##["), "{line:?}");
+            }
+            Provider::TeamCity => prop_assert!(!line.contains("##teamcity["), "{line:?}"),
+            _ => prop_assert!(line.starts_with(title.as_str()), "{line:?}"),
+        }
+    }
+
+    #[test]
+    fn github_annotations_stay_on_one_line(
+        message in text(),
+        file in text(),
+        title in text(),
+        line in any::<u32>(),
+    ) {
+        let rendered = Annotation::error(&message)
+            .file(&file)
+            .line(line)
+            .title(&title)
+            .render(Provider::GitHub);
+        prop_assert!(!rendered.contains(['\r', '\n']), "{rendered:?}");
+
+        // Properties can't add a separator of their own.
+        let (props, _) = rendered
+            .strip_prefix("::error ")
+            .and_then(|rest| rest.split_once("::"))
+            .unwrap();
+        prop_assert_eq!(props.matches(',').count(), 2, "{}", rendered);
+        prop_assert!(props.starts_with("file=") && props.contains(",title="));
+    }
+
+    #[test]
+    fn azure_annotations_cannot_start_commands(message in text()) {
+        for annotation in [Annotation::error(&message), Annotation::notice(&message)] {
+            let rendered = annotation.render(Provider::Azure);
+            for line in rendered.split(['\r', '\n']) {
+                let text = line.strip_prefix("##[error]").unwrap_or(line);
+                prop_assert!(!text.contains("##[") && !text.contains("##vso["), "{rendered:?}");
+            }
+        }
+    }
+}